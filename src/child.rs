@@ -0,0 +1,137 @@
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// signals forwarded to the child process
+pub const FORWARDED_SIGNALS: [libc::c_int; 6] = [
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+// process id of the running child process or 0
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+// a signal terminating the child process was forwarded
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+// without a running child process, the signal is handled by this process
+extern "C" fn forward(sig: libc::c_int) {
+    let pid = CHILD.load(Ordering::SeqCst);
+    if pid > 0 {
+        if sig != libc::SIGHUP && sig != libc::SIGUSR1 && sig != libc::SIGUSR2 {
+            TERMINATED.store(true, Ordering::SeqCst);
+        }
+        unsafe {
+            libc::kill(pid, sig);
+        }
+    } else {
+        unsafe {
+            libc::signal(sig, libc::SIG_DFL);
+            libc::raise(sig);
+        }
+    }
+}
+
+/// returns true if an interrupt, quit or terminate signal was forwarded to
+/// the child process
+pub fn terminated() -> bool {
+    TERMINATED.load(Ordering::SeqCst)
+}
+
+/// run a command with stdout and stderr piped
+///
+/// Signals received by this process are forwarded to the command.
+pub fn spawn(command: &[String]) -> io::Result<Child> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command"))?;
+
+    // signals received before the child process id is stored are handled
+    // by this process
+    for sig in FORWARDED_SIGNALS {
+        unsafe {
+            libc::signal(sig, forward as *const () as libc::sighandler_t);
+        }
+    }
+
+    let child = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    CHILD.store(child.id() as i32, Ordering::SeqCst);
+
+    Ok(child)
+}
+
+/// wait for the child process to exit
+pub fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+    let status = child.wait()?;
+    CHILD.store(0, Ordering::SeqCst);
+    Ok(status)
+}
+
+/// shell convention exit code: the exit status or 128 + the signal number
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(sig)) => 128 + sig,
+        (None, None) => 1,
+    }
+}
+
+/// shell convention exit code of a command that could not be run: 127 if
+/// the command was not found, 126 otherwise
+pub fn spawn_exit_code(err: &io::Error) -> i32 {
+    match err.kind() {
+        io::ErrorKind::NotFound => 127,
+        _ => 126,
+    }
+}
+
+/// describe why the command could not be run
+pub fn describe_error(program: &str, err: &io::Error) -> String {
+    // the OS error is described without the error number
+    let err = err.to_string();
+    let reason = match err.rfind(" (os error ") {
+        Some(n) => &err[..n],
+        None => &err,
+    };
+    format!("{}: {}", program, reason)
+}
+
+/// describe how the child process exited
+pub fn describe(program: &str, status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("{} exited with status {}", program, code),
+        (None, Some(sig)) => format!("{} terminated by signal {}", program, sig),
+        (None, None) => format!("{} exited", program),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_error() {
+        let err = spawn(&["/nonexistent/command".to_string()]).unwrap_err();
+        assert_eq!(spawn_exit_code(&err), 127);
+        assert_eq!(
+            describe_error("/nonexistent/command", &err),
+            "/nonexistent/command: No such file or directory"
+        );
+
+        let err = spawn(&["/".to_string()]).unwrap_err();
+        assert_eq!(spawn_exit_code(&err), 126);
+
+        let err = io::Error::other("custom");
+        assert_eq!(describe_error("cmd", &err), "cmd: custom");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::input::Record;
+use std::time::{Duration, Instant};
+
+/// collapses repeated messages
+///
+/// A record repeats the previous record if the message, severity, host and
+/// instances are identical. Repeats are counted and summarized as `last
+/// message repeated N times` when a different record is received or the
+/// window has passed since the first repeat.
+#[derive(Debug, Default)]
+pub struct Dedup {
+    last: Option<Record>,
+    count: usize,
+    t0: Option<Instant>,
+    summary: Option<Record>,
+}
+
+impl Dedup {
+    pub fn new() -> Self {
+        Dedup::default()
+    }
+
+    /// returns true if the record repeats the previous record
+    pub fn repeated(&mut self, record: &Record, now: Instant) -> bool {
+        if self.last.as_ref().is_some_and(|last| same(last, record)) {
+            self.count += 1;
+            self.t0.get_or_insert(now);
+            return true;
+        }
+
+        self.summary = self.summarize();
+        self.last = Some(record.clone());
+        false
+    }
+
+    /// end of the current repeat window
+    pub fn deadline(&self, window: Duration) -> Option<Instant> {
+        self.t0.map(|t0| t0 + window)
+    }
+
+    /// summary of the repeated messages
+    pub fn take(&mut self) -> Option<Record> {
+        self.summary.take().or_else(|| self.summarize())
+    }
+
+    fn summarize(&mut self) -> Option<Record> {
+        let last = self.last.as_ref().filter(|_| self.count > 0)?;
+
+        let summary = Record {
+            message: format!("last message repeated {} times", self.count),
+            severity: last.severity,
+            host: last.host.clone(),
+            plugin_instance: last.plugin_instance.clone(),
+            type_instance: last.type_instance.clone(),
+            ..Default::default()
+        };
+
+        self.count = 0;
+        self.t0 = None;
+        Some(summary)
+    }
+}
+
+fn same(a: &Record, b: &Record) -> bool {
+    a.message == b.message
+        && a.severity == b.severity
+        && a.host == b.host
+        && a.plugin_instance == b.plugin_instance
+        && a.type_instance == b.type_instance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::Severity;
+
+    fn record(message: &str) -> Record {
+        Record {
+            message: message.to_string(),
+            severity: Some(Severity::Warning),
+            host: Some("host".to_string()),
+            type_instance: Some("stderr".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single() {
+        let mut d = Dedup::new();
+        let t0 = Instant::now();
+
+        assert!(!d.repeated(&record("a"), t0));
+        assert!(!d.repeated(&record("b"), t0));
+        assert_eq!(d.take(), None);
+        assert_eq!(d.deadline(Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn different_message() {
+        let mut d = Dedup::new();
+        let t0 = Instant::now();
+
+        assert!(!d.repeated(&record("a"), t0));
+        assert!(d.repeated(&record("a"), t0));
+        assert!(d.repeated(&record("a"), t0));
+        assert!(!d.repeated(&record("b"), t0));
+
+        let summary = d.take().unwrap();
+        assert_eq!(summary.message, "last message repeated 2 times");
+        assert_eq!(summary.severity, Some(Severity::Warning));
+        assert_eq!(summary.host.as_deref(), Some("host"));
+        assert_eq!(summary.type_instance.as_deref(), Some("stderr"));
+        assert_eq!(d.take(), None);
+
+        // the different message is the new previous record
+        assert!(d.repeated(&record("b"), t0));
+    }
+
+    #[test]
+    fn different_fields() {
+        let mut d = Dedup::new();
+        let t0 = Instant::now();
+
+        assert!(!d.repeated(&record("a"), t0));
+
+        let r = Record {
+            severity: Some(Severity::Failure),
+            ..record("a")
+        };
+        assert!(!d.repeated(&r, t0));
+
+        let r = Record {
+            type_instance: None,
+            ..record("a")
+        };
+        assert!(!d.repeated(&r, t0));
+        assert_eq!(d.take(), None);
+    }
+
+    #[test]
+    fn window() {
+        let mut d = Dedup::new();
+        let window = Duration::from_secs(10);
+        let t0 = Instant::now();
+
+        assert!(!d.repeated(&record("a"), t0));
+
+        // the window starts at the first repeat
+        let t1 = t0 + Duration::from_secs(1);
+        assert!(d.repeated(&record("a"), t1));
+        assert!(d.repeated(&record("a"), t1 + Duration::from_secs(5)));
+        assert_eq!(d.deadline(window), Some(t1 + window));
+
+        let summary = d.take().unwrap();
+        assert_eq!(summary.message, "last message repeated 2 times");
+        assert_eq!(d.deadline(window), None);
+
+        // repeats after the window are counted again
+        let t2 = t1 + window;
+        assert!(d.repeated(&record("a"), t2));
+        assert_eq!(d.deadline(window), Some(t2 + window));
+        assert_eq!(
+            d.take().map(|r| r.message),
+            Some("last message repeated 1 times".to_string())
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use regex::Regex;
+
+/// returns true if the message is selected by the filters
+///
+/// A message is selected if it matches any include regex, or no include
+/// regexes are given, and does not match any exclude regex.
+pub fn selected(include: &[Regex], exclude: &[Regex], message: &str) -> bool {
+    (include.is_empty() || include.iter().any(|regex| regex.is_match(message)))
+        && !exclude.iter().any(|regex| regex.is_match(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regexes(patterns: &[&str]) -> Vec<Regex> {
+        patterns.iter().map(|p| Regex::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn no_filters() {
+        assert!(selected(&[], &[], "message"));
+        assert!(selected(&[], &[], ""));
+    }
+
+    #[test]
+    fn include() {
+        let include = regexes(&["^error", "fatal"]);
+        assert!(selected(&include, &[], "error: disk full"));
+        assert!(selected(&include, &[], "a fatal error"));
+        assert!(!selected(&include, &[], "warning: disk full"));
+    }
+
+    #[test]
+    fn exclude() {
+        let exclude = regexes(&["debug", "^health"]);
+        assert!(!selected(&[], &exclude, "debug: x"));
+        assert!(!selected(&[], &exclude, "healthcheck ok"));
+        assert!(selected(&[], &exclude, "error: disk full"));
+    }
+
+    #[test]
+    fn exclude_precedence() {
+        // exclude regexes take precedence over include regexes
+        let include = regexes(&["error"]);
+        let exclude = regexes(&["timeout"]);
+        assert!(selected(&include, &exclude, "error: disk full"));
+        assert!(!selected(&include, &exclude, "error: timeout"));
+        assert!(!selected(&include, &exclude, "timeout"));
+    }
+}
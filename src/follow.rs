@@ -0,0 +1,366 @@
+use crate::DATA_MAX_LEN;
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// initial read position of a followed file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Start {
+    Beginning,
+    End,
+    /// inode and offset: the file is read from the beginning if the inode
+    /// does not match or the file is shorter than the offset
+    Resume {
+        ino: u64,
+        offset: u64,
+    },
+}
+
+impl FromStr for Start {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beginning" => Ok(Start::Beginning),
+            "end" => Ok(Start::End),
+            _ => Err(format!("invalid start position: {}", s)),
+        }
+    }
+}
+
+/// followed file: `[<instance>=]<path>`
+///
+/// The instance defaults to the file name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FollowPath {
+    pub instance: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for FollowPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (instance, path) = match s.split_once('=') {
+            Some((instance, path)) => (instance.to_string(), PathBuf::from(path)),
+            None => {
+                let path = PathBuf::from(s);
+                let name = path
+                    .file_name()
+                    .ok_or_else(|| format!("invalid path: {}", s))?;
+                (name.to_string_lossy().into_owned(), path)
+            }
+        };
+
+        if instance.is_empty() || instance.len() >= DATA_MAX_LEN || instance.contains('/') {
+            return Err(format!("invalid instance: {}", instance));
+        }
+
+        Ok(FollowPath { instance, path })
+    }
+}
+
+/// notification field set to the followed file instance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instance {
+    Plugin,
+    Type,
+}
+
+impl FromStr for Instance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plugin" => Ok(Instance::Plugin),
+            "type" => Ok(Instance::Type),
+            _ => Err(format!("invalid instance: {}", s)),
+        }
+    }
+}
+
+/// follows a file across rotation and truncation, like `tail -F`
+///
+/// At end of file, the path is checked: if the file was replaced, the new
+/// file is read from the beginning; if the file was truncated, it is read
+/// from the beginning. A missing file is retried and read from the
+/// beginning when it appears: the start position applies only if the file
+/// exists on the first read.
+///
+/// The max backlog limits the data read from the start position when the
+/// file is first opened: older data is skipped up to the next line.
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    start: Start,
+    max_backlog: Option<u64>,
+    reader: Option<BufReader<File>>,
+    ino: u64,
+    dev: u64,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl Follower {
+    pub fn new(path: &Path, start: Start) -> Self {
+        Follower {
+            path: path.to_path_buf(),
+            start,
+            max_backlog: None,
+            reader: None,
+            ino: 0,
+            dev: 0,
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// max bytes read from the start position
+    pub fn max_backlog(mut self, max_backlog: u64) -> Self {
+        self.max_backlog = Some(max_backlog);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// inode and offset of the next line
+    pub fn position(&self) -> (u64, u64) {
+        (self.ino, self.offset)
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let mut file = File::open(&self.path)?;
+        let meta = file.metadata()?;
+
+        // the start position and backlog apply to the first file only
+        let mut offset = match self.start {
+            Start::Beginning => 0,
+            Start::End => meta.len(),
+            Start::Resume { ino, offset } if ino == meta.ino() && offset <= meta.len() => offset,
+            Start::Resume { .. } => 0,
+        };
+        self.start = Start::Beginning;
+
+        // discard the partial line at the backlog limit, starting from the
+        // preceding byte in case the limit is at the start of a line
+        let skip = match self.max_backlog.take() {
+            Some(max) if meta.len() - offset > max && meta.len() - max > 0 => {
+                offset = meta.len() - max - 1;
+                true
+            }
+            _ => false,
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file);
+
+        if skip {
+            offset += reader.read_until(b'\n', &mut Vec::new())? as u64;
+        }
+
+        self.reader = Some(reader);
+        self.ino = meta.ino();
+        self.dev = meta.dev();
+        self.offset = offset;
+        self.partial.clear();
+
+        Ok(())
+    }
+
+    fn take(&mut self) -> String {
+        self.offset += self.partial.len() as u64;
+        let line = String::from_utf8_lossy(&self.partial).into_owned();
+        self.partial.clear();
+        line
+    }
+
+    fn replaced(&self, meta: &Metadata) -> bool {
+        meta.ino() != self.ino || meta.dev() != self.dev
+    }
+
+    /// read the next complete line
+    ///
+    /// Returns `None` if no line is available.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        if self.reader.is_none() {
+            match self.open() {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    // a file created after the first attempt is new: read
+                    // it from the beginning, like tail -F
+                    self.start = Start::Beginning;
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let reader = self.reader.as_mut().unwrap();
+
+        let n = reader.read_until(b'\n', &mut self.partial)?;
+        if n > 0 && self.partial.ends_with(b"\n") {
+            return Ok(Some(self.take()));
+        }
+
+        // end of file: check for rotation or truncation
+        match std::fs::metadata(&self.path) {
+            Ok(meta) if self.replaced(&meta) => {
+                if !self.partial.is_empty() {
+                    return Ok(Some(self.take()));
+                }
+                self.reader = None;
+                self.read_line()
+            }
+            Ok(meta) if meta.len() < self.offset + self.partial.len() as u64 => {
+                let reader = self.reader.as_mut().unwrap();
+                reader.seek(SeekFrom::Start(0))?;
+                self.offset = 0;
+                self.partial.clear();
+                Ok(None)
+            }
+            Ok(_) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    fn tmp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("collectd-prv-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn append(path: &Path, data: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn lines(f: &mut Follower) -> Vec<String> {
+        std::iter::from_fn(|| f.read_line().unwrap()).collect()
+    }
+
+    #[test]
+    fn start_end() {
+        let path = tmp("end.log");
+        append(&path, "old\n");
+
+        let mut f = Follower::new(&path, Start::End);
+        assert!(lines(&mut f).is_empty());
+
+        append(&path, "new\npart");
+        assert_eq!(lines(&mut f), vec!["new\n"]);
+        append(&path, "ial\n");
+        assert_eq!(lines(&mut f), vec!["partial\n"]);
+    }
+
+    #[test]
+    fn created_after_start() {
+        let path = tmp("late.log");
+
+        let mut f = Follower::new(&path, Start::End);
+        assert!(lines(&mut f).is_empty());
+
+        append(&path, "l1\nl2\n");
+        assert_eq!(lines(&mut f), vec!["l1\n", "l2\n"]);
+    }
+
+    #[test]
+    fn rotation() {
+        let path = tmp("rotate.log");
+        let rotated = tmp("rotate.log.1");
+        append(&path, "a\n");
+
+        let mut f = Follower::new(&path, Start::Beginning);
+        assert_eq!(lines(&mut f), vec!["a\n"]);
+
+        append(&path, "b\nunterminated");
+        fs::rename(&path, &rotated).unwrap();
+        append(&path, "c\n");
+
+        assert_eq!(lines(&mut f), vec!["b\n", "unterminated", "c\n"]);
+    }
+
+    #[test]
+    fn truncation() {
+        let path = tmp("truncate.log");
+        append(&path, "aaaa\n");
+
+        let mut f = Follower::new(&path, Start::Beginning);
+        assert_eq!(lines(&mut f), vec!["aaaa\n"]);
+
+        fs::write(&path, "").unwrap();
+        assert!(lines(&mut f).is_empty());
+        append(&path, "b\n");
+        assert_eq!(lines(&mut f), vec!["b\n"]);
+    }
+
+    #[test]
+    fn resume() {
+        let path = tmp("resume.log");
+        append(&path, "a\nb\n");
+
+        let mut f = Follower::new(&path, Start::Beginning);
+        assert_eq!(f.read_line().unwrap().unwrap(), "a\n");
+        let (ino, offset) = f.position();
+        assert_eq!(offset, 2);
+
+        let mut f = Follower::new(&path, Start::Resume { ino, offset });
+        assert_eq!(lines(&mut f), vec!["b\n"]);
+
+        // another file: read from the beginning
+        let mut f = Follower::new(
+            &path,
+            Start::Resume {
+                ino: ino + 1,
+                offset,
+            },
+        );
+        assert_eq!(lines(&mut f), vec!["a\n", "b\n"]);
+    }
+
+    #[test]
+    fn max_backlog() {
+        let path = tmp("backlog.log");
+        append(&path, "line1\nline2\nline3\n");
+
+        // the limit is at the start of a line
+        let mut f = Follower::new(&path, Start::Beginning).max_backlog(6);
+        assert_eq!(lines(&mut f), vec!["line3\n"]);
+
+        // the partial line at the limit is skipped
+        let mut f = Follower::new(&path, Start::Beginning).max_backlog(8);
+        assert_eq!(lines(&mut f), vec!["line3\n"]);
+        assert_eq!(f.position().1, 18);
+    }
+
+    #[test]
+    fn follow_path() {
+        let f: FollowPath = "/var/log/app.log".parse().unwrap();
+        assert_eq!(f.instance, "app.log");
+        assert_eq!(f.path, PathBuf::from("/var/log/app.log"));
+
+        let f: FollowPath = "web=/var/log/app.log".parse().unwrap();
+        assert_eq!(f.instance, "web");
+
+        assert!("a/b=/var/log/app.log".parse::<FollowPath>().is_err());
+        assert!("=/var/log/app.log".parse::<FollowPath>().is_err());
+    }
+}
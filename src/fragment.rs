@@ -0,0 +1,248 @@
+/// splits messages into `@id:n:total@` prefixed fragments
+///
+/// Messages longer than the max fragment length are split on character
+/// boundaries. Each fragmented message is assigned an id in the range
+/// `1..=max_id`, wrapping around.
+#[derive(Clone, Debug)]
+pub struct Fragmenter {
+    max_length: usize,
+    max_id: u64,
+    id: u64,
+}
+
+impl Fragmenter {
+    pub fn new(max_length: usize, max_id: u64) -> Self {
+        Fragmenter {
+            max_length: max_length.max(1),
+            max_id: max_id.max(1),
+            id: 1,
+        }
+    }
+
+    /// number of fragments required for a message
+    pub fn count(&self, message: &str) -> usize {
+        let mut start = 0;
+        let mut total = 0;
+        while start < message.len() {
+            start = fragment_end(message, start, self.max_length);
+            total += 1;
+        }
+        total
+    }
+
+    /// split a message into fragments
+    ///
+    /// The fragment id is consumed only if the message requires more than
+    /// one fragment.
+    pub fn fragment<'a>(&mut self, message: &'a str) -> Fragments<'a> {
+        let total = self.count(message);
+        let id = self.id;
+        if total > 1 {
+            self.id = (self.id % self.max_id) + 1;
+        }
+        Fragments {
+            message,
+            max_length: self.max_length,
+            id,
+            n: 0,
+            total,
+            start: 0,
+        }
+    }
+}
+
+/// iterator over the fragments of a message
+#[derive(Clone, Debug)]
+pub struct Fragments<'a> {
+    message: &'a str,
+    max_length: usize,
+    id: u64,
+    n: usize,
+    total: usize,
+    start: usize,
+}
+
+impl Iterator for Fragments<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.n >= self.total {
+            return None;
+        }
+
+        let end = fragment_end(self.message, self.start, self.max_length);
+        let data = &self.message[self.start..end];
+
+        self.start = end;
+        self.n += 1;
+
+        Some(if self.total > 1 {
+            format!("@{}:{}:{}@{}", self.id, self.n, self.total, data)
+        } else {
+            data.to_string()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.total - self.n;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Fragments<'_> {}
+
+fn fragment_end(message: &str, start: usize, max_length: usize) -> usize {
+    if message.len() - start <= max_length {
+        return message.len();
+    }
+
+    let mut end = start + max_length;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    if end == start {
+        // fragment length is smaller than the character: include it
+        end = start + max_length;
+        while !message.is_char_boundary(end) {
+            end += 1;
+        }
+    }
+
+    end
+}
+
+/// fragment header: `@id:n:total@`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub id: u64,
+    pub n: usize,
+    pub total: usize,
+}
+
+impl Header {
+    /// parse the fragment header, returning the header and the fragment data
+    pub fn parse(message: &str) -> Option<(Header, &str)> {
+        let rest = message.strip_prefix('@')?;
+        let end = rest.find('@')?;
+
+        let mut fields = rest[..end].splitn(3, ':');
+        let id = fields.next()?.parse().ok()?;
+        let n = fields.next()?.parse().ok()?;
+        let total = fields.next()?.parse().ok()?;
+
+        if n == 0 || n > total {
+            return None;
+        }
+
+        Some((Header { id, n, total }, &rest[end + 1..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(f: &mut Fragmenter, message: &str) -> Vec<String> {
+        let fragments: Vec<String> = f.fragment(message).collect();
+        assert_eq!(f.count(message), fragments.len());
+        fragments
+    }
+
+    #[test]
+    fn single() {
+        let mut f = Fragmenter::new(5, 99);
+        assert_eq!(fragments(&mut f, "abc"), vec!["abc"]);
+        assert_eq!(fragments(&mut f, "abcde"), vec!["abcde"]);
+
+        // the id is not consumed
+        assert_eq!(
+            fragments(&mut f, "abcdef"),
+            vec!["@1:1:2@abcde", "@1:2:2@f"]
+        );
+    }
+
+    #[test]
+    fn multiple() {
+        let mut f = Fragmenter::new(3, 99);
+        assert_eq!(
+            fragments(&mut f, "abcdef"),
+            vec!["@1:1:2@abc", "@1:2:2@def"]
+        );
+        assert_eq!(
+            fragments(&mut f, "abcdefghi"),
+            vec!["@2:1:3@abc", "@2:2:3@def", "@2:3:3@ghi"]
+        );
+        assert_eq!(
+            fragments(&mut f, "abcdefg"),
+            vec!["@3:1:3@abc", "@3:2:3@def", "@3:3:3@g"]
+        );
+    }
+
+    #[test]
+    fn char_boundary() {
+        // "é" and "€" are 2 and 3 bytes
+        let mut f = Fragmenter::new(3, 99);
+        assert_eq!(fragments(&mut f, "aéé"), vec!["@1:1:2@aé", "@1:2:2@é"]);
+        assert_eq!(fragments(&mut f, "€€"), vec!["@2:1:2@€", "@2:2:2@€"]);
+        assert_eq!(fragments(&mut f, "ab€"), vec!["@3:1:2@ab", "@3:2:2@€"]);
+
+        // fragment length smaller than a character
+        let mut f = Fragmenter::new(1, 99);
+        assert_eq!(
+            fragments(&mut f, "a€b"),
+            vec!["@1:1:3@a", "@1:2:3@€", "@1:3:3@b"]
+        );
+    }
+
+    #[test]
+    fn id_wraparound() {
+        let mut f = Fragmenter::new(1, 3);
+        let ids: Vec<String> = (0..5).map(|_| fragments(&mut f, "ab").remove(0)).collect();
+        assert_eq!(
+            ids,
+            vec!["@1:1:2@a", "@2:1:2@a", "@3:1:2@a", "@1:1:2@a", "@2:1:2@a"]
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut f = Fragmenter::new(4, 99);
+        let message = "first second third";
+        let mut data = String::new();
+        for (i, fragment) in f.fragment(message).enumerate() {
+            let (header, rest) = Header::parse(&fragment).unwrap();
+            assert_eq!(
+                header,
+                Header {
+                    id: 1,
+                    n: i + 1,
+                    total: 5
+                }
+            );
+            data.push_str(rest);
+        }
+        assert_eq!(data, message);
+    }
+
+    #[test]
+    fn header_invalid() {
+        assert_eq!(Header::parse("message"), None);
+        assert_eq!(Header::parse("@1:1:2 message"), None);
+        assert_eq!(Header::parse("@1:2@message"), None);
+        assert_eq!(Header::parse("@x:1:2@message"), None);
+        assert_eq!(Header::parse("@1:0:2@message"), None);
+        assert_eq!(Header::parse("@1:3:2@message"), None);
+        assert_eq!(
+            Header::parse("@1:2:2@@3:1:1@"),
+            Some((
+                Header {
+                    id: 1,
+                    n: 2,
+                    total: 2
+                },
+                "@3:1:1@"
+            ))
+        );
+    }
+}
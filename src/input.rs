@@ -0,0 +1,189 @@
+use crate::notification::{Meta, Severity};
+use crate::syslog;
+use chrono::DateTime;
+use std::str::FromStr;
+
+/// input line format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    Raw,
+    Syslog,
+    Json,
+    Regex,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(InputFormat::Raw),
+            "syslog" => Ok(InputFormat::Syslog),
+            "json" => Ok(InputFormat::Json),
+            "regex" => Ok(InputFormat::Regex),
+            _ => Err(format!("invalid input format: {}", s)),
+        }
+    }
+}
+
+/// line read from an input source
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    pub text: String,
+    /// index of the input source
+    pub source: usize,
+    /// inode and offset following the line in a followed file
+    pub position: Option<(u64, u64)>,
+    /// source address of a datagram
+    pub address: Option<String>,
+}
+
+impl Line {
+    /// remove the line ending: the text ends at the first NUL byte and a
+    /// trailing `\n`, `\r\n` or `\r` is removed
+    pub fn chomp(&mut self) {
+        if let Some(n) = self.text.find('\0') {
+            self.text.truncate(n);
+        }
+        if self.text.ends_with('\n') {
+            self.text.pop();
+        }
+        if self.text.ends_with('\r') {
+            self.text.pop();
+        }
+    }
+}
+
+/// notification fields set for lines read from an input source
+///
+/// The severity is the default for lines without a parsed severity.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Source {
+    pub severity: Option<Severity>,
+    pub plugin_instance: Option<String>,
+    pub type_instance: Option<String>,
+}
+
+/// message and notification fields parsed from an input line
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    pub message: String,
+    pub severity: Option<Severity>,
+    pub time: Option<u64>,
+    pub host: Option<String>,
+    pub plugin_instance: Option<String>,
+    pub type_instance: Option<String>,
+    pub meta: Vec<Meta>,
+}
+
+impl Record {
+    pub fn new(message: impl Into<String>) -> Self {
+        Record {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// map a level name or number to a severity
+///
+/// Numbers are syslog severities (0-7) or bunyan/pino levels (10-60).
+pub fn level(s: &str) -> Severity {
+    if let Ok(n) = s.parse::<u64>() {
+        return match n {
+            0..=7 => syslog::severity(n as u8),
+            50.. => Severity::Failure,
+            40.. => Severity::Warning,
+            _ => Severity::Okay,
+        };
+    }
+
+    match s.to_ascii_lowercase().as_str() {
+        "emerg" | "emergency" | "alert" | "crit" | "critical" | "fatal" | "panic" | "err"
+        | "error" | "failure" => Severity::Failure,
+        "warn" | "warning" => Severity::Warning,
+        _ => Severity::Okay,
+    }
+}
+
+/// parse an RFC 3339 timestamp or a number of seconds, milliseconds,
+/// microseconds or nanoseconds since the epoch
+pub fn timestamp(s: &str) -> Option<u64> {
+    match s.parse::<f64>() {
+        Ok(t) => epoch(t),
+        Err(_) => epoch(DateTime::parse_from_rfc3339(s).ok()?.timestamp() as f64),
+    }
+}
+
+/// seconds since the epoch from seconds, milliseconds, microseconds or
+/// nanoseconds
+pub fn epoch(t: f64) -> Option<u64> {
+    if !t.is_finite() || t < 0.0 {
+        return None;
+    }
+
+    let t = match t {
+        t if t >= 1e17 => t / 1e9,
+        t if t >= 1e14 => t / 1e6,
+        t if t >= 1e11 => t / 1e3,
+        t => t,
+    };
+
+    Some(t as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        for (s, severity) in [
+            ("error", Severity::Failure),
+            ("ERR", Severity::Failure),
+            ("Fatal", Severity::Failure),
+            ("panic", Severity::Failure),
+            ("warn", Severity::Warning),
+            ("WARNING", Severity::Warning),
+            ("info", Severity::Okay),
+            ("debug", Severity::Okay),
+            ("unknown", Severity::Okay),
+            // syslog severities
+            ("0", Severity::Failure),
+            ("3", Severity::Failure),
+            ("4", Severity::Warning),
+            ("6", Severity::Okay),
+            // bunyan/pino levels
+            ("10", Severity::Okay),
+            ("30", Severity::Okay),
+            ("40", Severity::Warning),
+            ("50", Severity::Failure),
+            ("60", Severity::Failure),
+        ] {
+            assert_eq!(level(s), severity, "{}", s);
+        }
+    }
+
+    #[test]
+    fn epochs() {
+        assert_eq!(epoch(1700000000.0), Some(1700000000));
+        assert_eq!(epoch(1700000000.9), Some(1700000000));
+        assert_eq!(epoch(1700000000123.0), Some(1700000000));
+        assert_eq!(epoch(1700000000123456.0), Some(1700000000));
+        assert_eq!(epoch(1700000000123456789.0), Some(1700000000));
+        assert_eq!(epoch(0.0), Some(0));
+        assert_eq!(epoch(-1.0), None);
+        assert_eq!(epoch(f64::NAN), None);
+        assert_eq!(epoch(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp("1700000000"), Some(1700000000));
+        assert_eq!(timestamp("1700000000123"), Some(1700000000));
+        assert_eq!(timestamp("2003-10-11T22:14:15Z"), Some(1065910455));
+        assert_eq!(timestamp("2003-10-11T22:14:15.003-01:00"), Some(1065914055));
+        assert_eq!(timestamp("2003-10-11 22:14:15"), None);
+        assert_eq!(timestamp("yesterday"), None);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::input;
+use crate::input::Record;
+use crate::notification::{Meta, MetaValue, Severity};
+use serde_json::{Map, Value};
+
+/// JSON object keys mapped to notification fields
+#[derive(Clone, Debug)]
+pub struct JsonKeys {
+    pub message: String,
+    pub severity: String,
+    pub time: String,
+    pub host: String,
+    /// add the remaining fields as notification meta data
+    pub meta: bool,
+}
+
+impl Default for JsonKeys {
+    fn default() -> Self {
+        JsonKeys {
+            message: "msg".to_string(),
+            severity: "level".to_string(),
+            time: "ts".to_string(),
+            host: "host".to_string(),
+            meta: false,
+        }
+    }
+}
+
+/// parse a JSON object
+///
+/// Returns `None` if the line is not a JSON object or does not contain the
+/// message key.
+pub fn parse(line: &str, keys: &JsonKeys) -> Option<Record> {
+    let mut obj: Map<String, Value> = serde_json::from_str(line).ok()?;
+
+    let message = match obj.remove(&keys.message)? {
+        Value::String(s) => s,
+        v => v.to_string(),
+    };
+
+    let severity = obj.remove(&keys.severity).and_then(|v| severity(&v));
+    let time = obj.remove(&keys.time).and_then(|v| time(&v));
+    let host = match obj.remove(&keys.host) {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    };
+
+    let meta = if keys.meta {
+        obj.into_iter().filter_map(|(k, v)| meta(k, v)).collect()
+    } else {
+        Vec::new()
+    };
+
+    Some(Record {
+        message,
+        severity,
+        time,
+        host,
+        meta,
+        ..Default::default()
+    })
+}
+
+fn severity(v: &Value) -> Option<Severity> {
+    match v {
+        Value::String(s) => Some(input::level(s)),
+        Value::Number(n) => Some(input::level(&n.to_string())),
+        _ => None,
+    }
+}
+
+fn time(v: &Value) -> Option<u64> {
+    match v {
+        Value::Number(n) => input::epoch(n.as_f64()?),
+        Value::String(s) => input::timestamp(s),
+        _ => None,
+    }
+}
+
+fn meta(key: String, v: Value) -> Option<Meta> {
+    // meta data values are strings: the collectd PUTNOTIF command may only
+    // accept string meta data
+    let value = match v {
+        Value::Null => return None,
+        Value::String(s) => MetaValue::String(s),
+        v => MetaValue::String(v.to_string()),
+    };
+
+    Meta::is_valid_key(&key).then(|| Meta::new(key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let keys = JsonKeys::default();
+        let r = parse(
+            r#"{"msg":"disk full","level":"warn","ts":1700000000123,"host":"web1","x":1}"#,
+            &keys,
+        )
+        .unwrap();
+        assert_eq!(r.message, "disk full");
+        assert_eq!(r.severity, Some(Severity::Warning));
+        assert_eq!(r.time, Some(1700000000));
+        assert_eq!(r.host.as_deref(), Some("web1"));
+        assert!(r.meta.is_empty());
+    }
+
+    #[test]
+    fn keys() {
+        let keys = JsonKeys {
+            message: "message".to_string(),
+            severity: "severity".to_string(),
+            time: "time".to_string(),
+            host: "hostname".to_string(),
+            meta: false,
+        };
+        let r = parse(
+            r#"{"message":"m","severity":50,"time":"2003-10-11T22:14:15Z","hostname":"h","msg":"x"}"#,
+            &keys,
+        )
+        .unwrap();
+        assert_eq!(r.message, "m");
+        assert_eq!(r.severity, Some(Severity::Failure));
+        assert_eq!(r.time, Some(1065910455));
+        assert_eq!(r.host.as_deref(), Some("h"));
+    }
+
+    #[test]
+    fn missing_fields() {
+        let keys = JsonKeys::default();
+        let r = parse(r#"{"msg":"m","level":[1],"ts":"never","host":1}"#, &keys).unwrap();
+        assert_eq!(r.severity, None);
+        assert_eq!(r.time, None);
+        assert_eq!(r.host, None);
+
+        // non-string messages are JSON text
+        let r = parse(r#"{"msg":{"a":1}}"#, &keys).unwrap();
+        assert_eq!(r.message, r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn not_json() {
+        let keys = JsonKeys::default();
+        assert!(parse(r#"{"message":"no msg key"}"#, &keys).is_none());
+        assert!(parse(r#"["msg"]"#, &keys).is_none());
+        assert!(parse("msg", &keys).is_none());
+        assert!(parse("", &keys).is_none());
+    }
+
+    #[test]
+    fn metas() {
+        let keys = JsonKeys {
+            meta: true,
+            ..Default::default()
+        };
+        let r = parse(
+            r#"{"msg":"m","s":"a","n":1.5,"b":true,"o":{"k":[1]},"z":null,"bad key":1}"#,
+            &keys,
+        )
+        .unwrap();
+
+        let mut meta: Vec<(String, MetaValue)> =
+            r.meta.into_iter().map(|m| (m.key, m.value)).collect();
+        meta.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let string = |s: &str| MetaValue::String(s.to_string());
+        assert_eq!(
+            meta,
+            vec![
+                ("b".to_string(), string("true")),
+                ("n".to_string(), string("1.5")),
+                ("o".to_string(), string(r#"{"k":[1]}"#)),
+                ("s".to_string(), string("a")),
+            ]
+        );
+    }
+}
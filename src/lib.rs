@@ -0,0 +1,56 @@
+//! collectd-prv: stdout to collectd notifications
+
+pub mod child;
+pub mod dedup;
+pub mod filter;
+pub mod follow;
+pub mod fragment;
+pub mod input;
+pub mod json;
+pub mod limiter;
+pub mod listen;
+pub mod metric;
+pub mod multiline;
+pub mod network;
+pub mod notification;
+pub mod output;
+pub mod pattern;
+pub mod reassemble;
+pub mod restart;
+pub mod rewrite;
+pub mod service;
+pub mod severity;
+pub mod signal;
+pub mod state;
+pub mod stats;
+pub mod syslog;
+pub mod unixsock;
+pub mod value;
+
+pub use dedup::Dedup;
+pub use follow::{FollowPath, Follower, Instance, Start};
+pub use fragment::{Fragmenter, Fragments, Header};
+pub use input::{InputFormat, Line, Record, Source};
+pub use json::JsonKeys;
+pub use limiter::{Discarded, Limiter, RateLimiter};
+pub use listen::{Listen, Listener};
+pub use metric::{Metric, MetricRule};
+pub use multiline::Multiline;
+pub use network::Network;
+pub use notification::{Meta, MetaValue, Notification, NotificationBuilder, Severity};
+pub use output::{Destination, Output, Stdout, WriteBuffer};
+pub use reassemble::{Decoded, Decoder, Lost, Reassembler};
+pub use restart::Restart;
+pub use rewrite::Rewrite;
+pub use service::Service;
+pub use severity::SeverityRule;
+pub use state::State;
+pub use stats::Stats;
+pub use unixsock::Unixsock;
+pub use value::{Value, ValueList};
+
+/// max length of a collectd plugin or type name
+pub const DATA_MAX_LEN: usize = 64;
+
+/// max length of the notification hostname
+pub const HOSTNAME_MAX_LEN: usize = 16;
@@ -0,0 +1,345 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// message rate limiter
+///
+/// Messages are weighted by the number of fragments.
+pub trait RateLimiter {
+    /// returns true if `n` fragments may be sent
+    fn allow(&mut self, n: usize, now: Instant) -> bool;
+
+    /// fragments counted against the limit in the current window
+    fn count(&self) -> usize;
+}
+
+/// rate limiter algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limiter {
+    Fixed,
+    TokenBucket,
+    SlidingWindow,
+}
+
+impl Limiter {
+    /// create a rate limiter allowing `limit` fragments per window
+    ///
+    /// The burst size applies to the token bucket limiter only.
+    pub fn build(self, limit: usize, window: Duration, burst: usize) -> Box<dyn RateLimiter> {
+        match self {
+            Limiter::Fixed => Box::new(FixedWindow::new(limit, window)),
+            Limiter::TokenBucket => Box::new(TokenBucket::new(limit, window, burst)),
+            Limiter::SlidingWindow => Box::new(SlidingWindow::new(limit, window)),
+        }
+    }
+}
+
+impl FromStr for Limiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Limiter::Fixed),
+            "token-bucket" => Ok(Limiter::TokenBucket),
+            "sliding-window" => Ok(Limiter::SlidingWindow),
+            _ => Err(format!("invalid limiter: {}", s)),
+        }
+    }
+}
+
+/// fixed window rate limiter
+///
+/// All fragments, including discarded fragments, are counted until the
+/// window resets.
+#[derive(Debug)]
+pub struct FixedWindow {
+    limit: usize,
+    window: Duration,
+    t0: Instant,
+    count: usize,
+}
+
+impl FixedWindow {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        FixedWindow {
+            limit,
+            window,
+            t0: Instant::now(),
+            count: 0,
+        }
+    }
+}
+
+impl RateLimiter for FixedWindow {
+    fn allow(&mut self, n: usize, now: Instant) -> bool {
+        if now.duration_since(self.t0) >= self.window {
+            self.count = 0;
+            self.t0 = now;
+        }
+
+        self.count += n;
+        self.count <= self.limit
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// token bucket rate limiter
+///
+/// The bucket holds up to `burst` tokens and refills at `limit` tokens per
+/// window. A message larger than the bucket is sent when the bucket is full
+/// and the deficit is repaid before the next message.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    t0: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: usize, window: Duration, burst: usize) -> Self {
+        let burst = burst.max(1) as f64;
+        TokenBucket {
+            rate: limit as f64 / window.as_secs_f64().max(f64::EPSILON),
+            burst,
+            tokens: burst,
+            t0: Instant::now(),
+        }
+    }
+}
+
+impl RateLimiter for TokenBucket {
+    fn allow(&mut self, n: usize, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.t0).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.t0 = now;
+
+        if self.tokens < (n as f64).min(self.burst) {
+            return false;
+        }
+
+        self.tokens -= n as f64;
+        true
+    }
+
+    fn count(&self) -> usize {
+        (self.burst - self.tokens).max(0.0).ceil() as usize
+    }
+}
+
+/// sliding window rate limiter
+///
+/// Sent fragments are counted over the preceding window.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    limit: usize,
+    window: Duration,
+    sent: VecDeque<(Instant, usize)>,
+    count: usize,
+}
+
+impl SlidingWindow {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        SlidingWindow {
+            limit,
+            window,
+            sent: VecDeque::new(),
+            count: 0,
+        }
+    }
+}
+
+impl RateLimiter for SlidingWindow {
+    fn allow(&mut self, n: usize, now: Instant) -> bool {
+        while let Some(&(t, k)) = self.sent.front() {
+            if now.duration_since(t) < self.window {
+                break;
+            }
+            self.sent.pop_front();
+            self.count -= k;
+        }
+
+        if self.count + n > self.limit {
+            return false;
+        }
+
+        self.sent.push_back((now, n));
+        self.count += n;
+        true
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// max length of the sample messages in the discard summary
+const SAMPLE_MAX_LEN: usize = 64;
+
+/// messages discarded by the rate limiter
+///
+/// Discarded messages are summarized once per window, starting from the
+/// first discarded message.
+#[derive(Debug, Default)]
+pub struct Discarded {
+    messages: usize,
+    fragments: usize,
+    first: String,
+    last: String,
+    t0: Option<Instant>,
+}
+
+impl Discarded {
+    pub fn new() -> Self {
+        Discarded::default()
+    }
+
+    pub fn add(&mut self, message: &str, fragments: usize, now: Instant) {
+        if self.t0.is_none() {
+            self.t0 = Some(now);
+            self.first = sample(message);
+        }
+        self.last = sample(message);
+        self.messages += 1;
+        self.fragments += fragments;
+    }
+
+    /// end of the current summary window
+    pub fn deadline(&self, window: Duration) -> Option<Instant> {
+        self.t0.map(|t0| t0 + window)
+    }
+
+    /// summary message of the discarded messages
+    pub fn take(&mut self) -> Option<String> {
+        self.t0?;
+
+        let summary = format!(
+            "discarded {} messages ({} fragments): first: \"{}\" last: \"{}\"",
+            self.messages, self.fragments, self.first, self.last
+        );
+
+        *self = Discarded::default();
+        Some(summary)
+    }
+}
+
+fn sample(message: &str) -> String {
+    let mut end = message.len().min(SAMPLE_MAX_LEN);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    message[..end].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn fixed_window_edge() {
+        let mut l = FixedWindow::new(10, SECOND);
+        let t0 = Instant::now();
+
+        assert!(l.allow(10, t0 + ms(900)));
+        assert!(!l.allow(1, t0 + ms(950)));
+
+        // discarded fragments are counted
+        assert_eq!(l.count(), 11);
+
+        // twice the limit is allowed across the window edge
+        assert!(l.allow(10, t0 + ms(1000)));
+        assert_eq!(l.count(), 10);
+    }
+
+    #[test]
+    fn sliding_window_edge() {
+        let mut l = SlidingWindow::new(10, SECOND);
+        let t0 = Instant::now();
+
+        assert!(l.allow(10, t0 + ms(900)));
+        assert!(!l.allow(1, t0 + ms(950)));
+
+        // discarded fragments are not counted
+        assert_eq!(l.count(), 10);
+
+        // the limit applies across the fixed window edge
+        assert!(!l.allow(1, t0 + ms(1000)));
+        assert!(!l.allow(1, t0 + ms(1899)));
+        assert!(l.allow(10, t0 + ms(1900)));
+        assert!(!l.allow(1, t0 + ms(1900)));
+    }
+
+    #[test]
+    fn sliding_window_expiry() {
+        let mut l = SlidingWindow::new(10, SECOND);
+        let t0 = Instant::now();
+
+        assert!(l.allow(4, t0));
+        assert!(l.allow(6, t0 + ms(500)));
+        assert!(!l.allow(1, t0 + ms(999)));
+
+        // only the fragments sent at t0 have expired
+        assert!(l.allow(4, t0 + ms(1000)));
+        assert!(!l.allow(1, t0 + ms(1000)));
+        assert_eq!(l.count(), 10);
+    }
+
+    #[test]
+    fn token_bucket_burst() {
+        let mut l = TokenBucket::new(10, SECOND, 5);
+        let t0 = Instant::now();
+
+        assert!(l.allow(5, t0));
+        assert!(!l.allow(1, t0));
+        assert_eq!(l.count(), 5);
+    }
+
+    #[test]
+    fn token_bucket_refill() {
+        let mut l = TokenBucket::new(10, SECOND, 5);
+        let t0 = Instant::now();
+
+        assert!(l.allow(5, t0));
+
+        // 10 tokens per second
+        assert!(l.allow(2, t0 + ms(200)));
+        assert!(!l.allow(1, t0 + ms(200)));
+
+        // the bucket is refilled up to the burst size
+        assert!(l.allow(5, t0 + ms(10_000)));
+        assert!(!l.allow(1, t0 + ms(10_000)));
+    }
+
+    #[test]
+    fn token_bucket_oversize() {
+        let mut l = TokenBucket::new(10, SECOND, 5);
+        let t0 = Instant::now();
+
+        // a message larger than the bucket is sent when the bucket is full
+        assert!(l.allow(8, t0));
+
+        // the deficit of 3 tokens is repaid before the next message
+        assert!(!l.allow(1, t0 + ms(300)));
+        assert!(l.allow(1, t0 + ms(400)));
+
+        // a partially refilled bucket does not send an oversize message
+        assert!(!l.allow(8, t0 + ms(700)));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("fixed".parse(), Ok(Limiter::Fixed));
+        assert_eq!("token-bucket".parse(), Ok(Limiter::TokenBucket));
+        assert_eq!("sliding-window".parse(), Ok(Limiter::SlidingWindow));
+        assert!("leaky-bucket".parse::<Limiter>().is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::io;
+use std::net::UdpSocket;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// max datagram size
+pub const DATAGRAM_MAX_LEN: usize = 65535;
+
+/// datagram socket address: `unixgram:<path>` or `udp:<host>:<port>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listen {
+    Unixgram(PathBuf),
+    Udp(String),
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("unixgram", path)) if !path.is_empty() => {
+                Ok(Listen::Unixgram(PathBuf::from(path)))
+            }
+            Some(("udp", addr)) if !addr.is_empty() => Ok(Listen::Udp(addr.to_string())),
+            _ => Err(format!("invalid listen address: {}", s)),
+        }
+    }
+}
+
+impl Listen {
+    /// bind the socket
+    ///
+    /// A stale socket at the UNIX socket path is removed.
+    pub fn bind(&self) -> io::Result<Listener> {
+        match self {
+            Listen::Unixgram(path) => {
+                if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    fs::remove_file(path)?;
+                }
+                Ok(Listener::Unixgram(UnixDatagram::bind(path)?))
+            }
+            Listen::Udp(addr) => Ok(Listener::Udp(UdpSocket::bind(addr)?)),
+        }
+    }
+}
+
+/// bound datagram socket
+#[derive(Debug)]
+pub enum Listener {
+    Unixgram(UnixDatagram),
+    Udp(UdpSocket),
+}
+
+impl Listener {
+    /// receive a datagram, returning the length and the source address
+    ///
+    /// Unbound UNIX sockets do not have a source address.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, Option<String>)> {
+        match self {
+            Listener::Unixgram(socket) => {
+                let (n, addr) = socket.recv_from(buf)?;
+                Ok((n, addr.as_pathname().map(|p| p.display().to_string())))
+            }
+            Listener::Udp(socket) => {
+                let (n, addr) = socket.recv_from(buf)?;
+                Ok((n, Some(addr.to_string())))
+            }
+        }
+    }
+}
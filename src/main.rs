@@ -1,26 +1,139 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use collectd_prv::multiline::Mode;
+use collectd_prv::network::{Credentials, SecurityLevel};
+use collectd_prv::{child, filter, json, listen, notification, pattern, severity, signal, syslog};
+use collectd_prv::{
+    Decoded, Decoder, Dedup, Destination, Discarded, FollowPath, Follower, Fragmenter, InputFormat,
+    Instance, JsonKeys, Limiter, Line, Listen, Lost, Meta, MetaValue, Metric, MetricRule,
+    Multiline, Network, Notification, Output, RateLimiter, Reassembler, Record, Restart, Rewrite,
+    Service, Severity, SeverityRule, Source, Start, State, Stats, Stdout, Unixsock, ValueList,
+    WriteBuffer, DATA_MAX_LEN, HOSTNAME_MAX_LEN,
+};
 use gethostname::gethostname;
-use std::error::Error;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::Write;
-use std::process::exit;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Child, ExitStatus};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-const DATA_MAX_LEN: usize = 64;
-const HOSTNAME_MAX_LEN: usize = 16;
+/// exit status if the write buffer is full and the write buffer policy is
+/// `exit`
+const EXIT_WRITE_BUFFER_FULL: i32 = 75;
+
+/// interval between checks of followed files at end of file
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// delay after a datagram receive error
+const LISTEN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// lines read ahead of the output: readers block when the output is full
+const READ_AHEAD: usize = 64;
+
+/// lines read from the input sources
+type Lines = mpsc::Receiver<io::Result<Line>>;
+
+// check for signals while waiting for input
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// stdout to collectd notifications
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// collectd service: <plugin>/<type>
-    #[clap(short, long, default_value = "stdout/prv", value_parser = parse_service::<String, String>)]
-    service: (String, String),
+    /// collectd service: <plugin>[-<plugin_instance>]/<type>[-<type_instance>]
+    #[clap(short, long, default_value = "stdout/prv")]
+    service: Service,
 
     /// system hostname
     #[clap(short = 'H', long, default_value = "")]
     hostname: String,
 
+    /// input format: raw, syslog, json, regex
+    #[clap(short = 'f', long = "input-format", default_value = "raw")]
+    input_format: InputFormat,
+
+    /// regex input format: named capture groups set notification fields
+    #[clap(long = "input-regex", required_if_eq("input-format", "regex"))]
+    input_regex: Option<Regex>,
+
+    /// JSON message key
+    #[clap(long = "json-message-key", default_value = "msg")]
+    json_message_key: String,
+
+    /// JSON severity key
+    #[clap(long = "json-severity-key", default_value = "level")]
+    json_severity_key: String,
+
+    /// JSON time key
+    #[clap(long = "json-time-key", default_value = "ts")]
+    json_time_key: String,
+
+    /// JSON hostname key
+    #[clap(long = "json-host-key", default_value = "host")]
+    json_host_key: String,
+
+    /// add unknown JSON fields as notification meta data
+    #[clap(long = "json-meta")]
+    json_meta: bool,
+
+    /// read lines from a file instead of stdin: [<instance>=]<path>
+    #[clap(short = 'F', long = "follow")]
+    follow: Vec<FollowPath>,
+
+    /// read datagrams instead of stdin: unixgram:<path>, udp:<host>:<port>
+    #[clap(long, conflicts_with_all = &["follow", "exec"])]
+    listen: Vec<Listen>,
+
+    /// followed file instance field: plugin, type
+    #[clap(long = "follow-instance", default_value = "type")]
+    follow_instance: Instance,
+
+    /// followed file start position: beginning, end
+    #[clap(long = "follow-start", default_value = "end")]
+    follow_start: Start,
+
+    /// save followed file positions and resume from the saved positions
+    #[clap(long = "state-file", requires = "follow")]
+    state_file: Option<PathBuf>,
+
+    /// max bytes read from the start position of a followed file
+    #[clap(long = "max-backlog", requires = "follow")]
+    max_backlog: Option<u64>,
+
+    /// multi-line records: lines matching the regex continue the record
+    #[clap(long = "multiline-continue", conflicts_with = "multiline-start")]
+    multiline_continue: Option<Regex>,
+
+    /// multi-line records: lines matching the regex begin a record
+    #[clap(long = "multiline-start")]
+    multiline_start: Option<Regex>,
+
+    /// multi-line record flush timeout (seconds)
+    #[clap(long = "multiline-timeout", default_value_t = 1.0, value_parser = parse_interval)]
+    multiline_timeout: f64,
+
+    /// multi-line record line separator
+    #[clap(long = "multiline-separator", default_value = " ")]
+    multiline_separator: String,
+
+    /// max lines in a multi-line record
+    #[clap(long = "multiline-max-lines", default_value_t = 500)]
+    multiline_max_lines: usize,
+
+    /// notification meta data: [s:]<key>=<value>
+    #[clap(long)]
+    meta: Vec<Meta>,
+
+    /// use the hostname from the input
+    #[clap(long = "input-host")]
+    input_host: bool,
+
     /// message rate limit
     #[clap(short, long, default_value_t = 0)]
     limit: usize,
@@ -29,6 +142,38 @@ struct Args {
     #[clap(short, long, default_value_t = 1)]
     window: u64,
 
+    /// send only messages matching a regex
+    #[clap(long)]
+    include: Vec<Regex>,
+
+    /// discard messages matching a regex
+    #[clap(long)]
+    exclude: Vec<Regex>,
+
+    /// rewrite messages: s/<regex>/<replacement>/[gi]
+    #[clap(long = "rewrite")]
+    rewrites: Vec<Rewrite>,
+
+    /// collapse repeated messages, summarizing after the window (seconds)
+    #[clap(long, default_value_t = 0)]
+    dedup: u64,
+
+    /// rate limiter: fixed, token-bucket, sliding-window
+    #[clap(long, default_value = "fixed")]
+    limiter: Limiter,
+
+    /// token bucket burst size (default: limit)
+    #[clap(long)]
+    burst: Option<usize>,
+
+    /// default notification severity: failure, warning, okay
+    #[clap(long, default_value = "okay")]
+    severity: Severity,
+
+    /// assign severity to lines matching a regex: <severity>=<regex>
+    #[clap(long = "severity-rule")]
+    severity_rules: Vec<SeverityRule>,
+
     /// max message fragment length
     #[clap(short = 'M', long = "max-event-length", default_value_t = 245)]
     max_event_length: usize, // 255 - 10
@@ -37,34 +182,104 @@ struct Args {
     #[clap(short = 'I', long = "max-event-id", default_value_t = 99)]
     max_event_id: u64,
 
-    /// behaviour if write buffer is full
+    /// output: stdout, unix:<path>, udp:<host>:<port>
+    #[clap(short, long, default_value = "stdout")]
+    output: Destination,
+
+    /// udp output security level: none, sign, encrypt
+    #[clap(long = "security-level", default_value = "none")]
+    security_level: SecurityLevel,
+
+    /// udp output username (default: first user in the auth file)
+    #[clap(long)]
+    username: Option<String>,
+
+    /// udp output credentials: <username>: <password>
+    #[clap(long = "auth-file")]
+    auth_file: Option<PathBuf>,
+
+    /// behaviour if write buffer is full: exit, drop, block
     #[clap(short = 'W', long = "write-buffer", default_value = "block")]
-    write_buffer: String,
+    write_buffer: WriteBuffer,
+
+    /// extract PUTVAL metrics from lines: <name>:<type>:<regex>
+    #[clap(long = "metric")]
+    metrics: Vec<MetricRule>,
+
+    /// emit PUTVAL statistics
+    #[clap(long)]
+    stats: bool,
+
+    /// statistics and metrics interval (seconds)
+    #[clap(long, env = "COLLECTD_INTERVAL", default_value_t = 10.0, value_parser = parse_interval)]
+    interval: f64,
 
     /// verbose mode
     #[clap(short, long)]
     verbose: bool,
+
+    /// command severity of lines read from stderr
+    #[clap(long = "stderr-severity", default_value = "warning")]
+    stderr_severity: Severity,
+
+    /// command type instance of lines read from stderr
+    #[clap(long = "stderr-type-instance", default_value = "stderr", value_parser = parse_instance)]
+    stderr_type_instance: String,
+
+    /// restart the command when it exits
+    #[clap(long, requires = "exec")]
+    restart: bool,
+
+    /// initial command restart delay (seconds)
+    #[clap(long = "restart-delay", default_value_t = 1.0, value_parser = parse_interval)]
+    restart_delay: f64,
+
+    /// max command restart delay (seconds)
+    #[clap(long = "restart-max-delay", default_value_t = 60.0, value_parser = parse_interval)]
+    restart_max_delay: f64,
+
+    /// max command restarts in the restart window (default: no limit)
+    #[clap(long = "max-restarts", default_value_t = 0)]
+    max_restarts: usize,
+
+    /// command restart window (seconds)
+    #[clap(long = "restart-window", default_value_t = 60)]
+    restart_window: u64,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// run a command and read its stdout and stderr instead of stdin
+    #[clap(last = true, conflicts_with = "follow")]
+    exec: Vec<String>,
 }
 
-fn parse_service<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
-where
-    T: std::str::FromStr,
-    T::Err: Error + Send + Sync + 'static,
-    U: std::str::FromStr,
-    U::Err: Error + Send + Sync + 'static,
-{
-    let pos = s
-        .find('/')
-        .ok_or_else(|| format!("invalid plugin/type: no `/` found in `{}`", s))?;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// rejoin fragmented notifications
+    Reassemble {
+        /// discard incomplete messages after timeout (seconds)
+        #[clap(short, long, default_value_t = 30)]
+        expire: u64,
 
-    if pos >= DATA_MAX_LEN || s[pos + 1..].len() >= DATA_MAX_LEN {
-        Err(format!("invalid service: {}", s))?;
-    }
+        /// save incomplete messages on exit and restore them on start
+        #[clap(long = "state-file")]
+        state_file: Option<PathBuf>,
+    },
+}
 
-    let plugin = s[..pos].parse()?;
-    let ctype = s[pos + 1..].parse()?;
+fn parse_interval(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(format!("invalid interval: {}", s)),
+    }
+}
 
-    Ok((plugin, ctype))
+fn parse_instance(s: &str) -> Result<String, String> {
+    if s.len() >= DATA_MAX_LEN || s.contains('/') {
+        return Err(format!("invalid instance: {}", s));
+    }
+    Ok(s.to_string())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -79,103 +294,950 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.hostname = gethostname().into_string().unwrap();
     }
 
-    event_loop(&args)
-}
+    let result = match args.command {
+        Some(Command::Reassemble {
+            expire,
+            ref state_file,
+        }) => reassemble(&args, expire, state_file.as_deref()),
+        None => event_loop(&args),
+    };
 
-fn event_loop(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
-    let (plugin, ctype) = &args.service;
+    if let Err(err) = &result {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            if err.kind() == io::ErrorKind::WouldBlock {
+                eprintln!("write buffer full");
+                exit(EXIT_WRITE_BUFFER_FULL)
+            }
+            if err.kind() == io::ErrorKind::Interrupted {
+                eprintln!("{}", err);
+                exit(match signal::received() {
+                    Some(sig) => 128 + sig,
+                    None => 1,
+                })
+            }
+        }
+    }
 
-    let mut stdout = io::stdout();
-    let stdin = io::stdin();
+    // exit with the command exit status
+    let code = result?;
+    if code != 0 {
+        exit(code)
+    }
 
-    let mut t0 = Instant::now();
+    Ok(())
+}
 
-    let mut count = 0;
-    let mut id = 1;
+fn event_loop(args: &Args) -> Result<i32, Box<dyn std::error::Error>> {
+    let state = args.state_file.as_deref().map(State::load).transpose()?;
+    let mut child = None;
+    let mut started = Instant::now();
+    let mut restart = args.restart.then(|| {
+        Restart::new(
+            Duration::from_secs_f64(args.restart_delay),
+            Duration::from_secs_f64(args.restart_max_delay),
+            args.max_restarts,
+            Duration::from_secs(args.restart_window),
+        )
+    });
+    let mut lines = if !args.exec.is_empty() {
+        match exec(args) {
+            Ok((c, lines)) => {
+                child = Some(c);
+                lines
+            }
+            Err(err) => {
+                let mut prv = Prv::new(args, state)?;
+                prv.spawn_failed(&err, "")?;
+                prv.close()?;
+                return Ok(child::spawn_exit_code(&err));
+            }
+        }
+    } else if !args.follow.is_empty() {
+        follow(args, state.as_ref())
+    } else if !args.listen.is_empty() {
+        listen(args)?
+    } else {
+        read_lines()
+    };
+    let mut prv = Prv::new(args, state)?;
 
-    let mut buf = String::new();
+    // without a command, interrupt and terminate signals stop reading the
+    // input: the pending records are sent before exiting
+    let catch = child.is_none();
+    if catch {
+        signal::catch(&signal::EXIT_SIGNALS);
+    }
 
     loop {
-        buf.clear();
+        let deadline = match prv.deadline() {
+            Some(t) if catch => Some(t.min(Instant::now() + SIGNAL_INTERVAL)),
+            None if catch => Some(Instant::now() + SIGNAL_INTERVAL),
+            deadline => deadline,
+        };
+
+        match recv_until(&lines, deadline) {
+            Ok(line) => prv.line(line?)?,
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                prv.drain()?;
+                let code = match &mut child {
+                    Some(c) => {
+                        let status = child::wait(c)?;
+
+                        // commands stopped by a forwarded signal are not
+                        // restarted
+                        let delay = match &mut restart {
+                            Some(r) if !child::terminated() => r.next(started, Instant::now()),
+                            _ => None,
+                        };
+
+                        match delay {
+                            Some(delay) => {
+                                prv.exited(
+                                    status,
+                                    &format!("restarting in {}s", delay.as_secs_f64()),
+                                )?;
+                                let r = restart.as_mut().expect("restart policy");
+                                match respawn(args, &mut prv, r, delay)? {
+                                    Ok((c, l)) => {
+                                        child = Some(c);
+                                        lines = l;
+                                        started = Instant::now();
+                                        continue;
+                                    }
+                                    Err(code) => code,
+                                }
+                            }
+                            None if restart.is_some() && !child::terminated() => {
+                                prv.exited(status, "restart limit reached")?;
+                                child::exit_code(status)
+                            }
+                            None => {
+                                prv.exited(status, "")?;
+                                child::exit_code(status)
+                            }
+                        }
+                    }
+                    None => 0,
+                };
+                prv.close()?;
+                return Ok(code);
+            }
+        }
+
+        // batch pending lines before flushing the output
+        while let Ok(line) = lines.try_recv() {
+            prv.line(line?)?;
+        }
+
+        prv.tick(Instant::now())?;
+        prv.output.flush()?;
+        prv.checkpoint()?;
+
+        if let Some(sig) = signal::received().filter(|_| catch) {
+            prv.close()?;
+            return Ok(128 + sig);
+        }
+    }
+}
+
+struct Prv<'a> {
+    args: &'a Args,
+    output: Box<dyn Output>,
+    limiter: Box<dyn RateLimiter>,
+    fragmenter: Fragmenter,
+    discarded: Discarded,
+    dedup: Dedup,
+    json_keys: JsonKeys,
+    sources: Vec<Source>,
+    multiline: Option<Multiline>,
+    // multi-line records by input source and datagram source address
+    pending: HashMap<(usize, Option<String>), Multiline>,
+    state: Option<State>,
+    stats: Stats,
+    metrics: Vec<Metric>,
+    next_interval: Option<Instant>,
+}
+
+impl<'a> Prv<'a> {
+    fn new(args: &'a Args, state: Option<State>) -> io::Result<Self> {
+        Ok(Prv {
+            args,
+            output: match &args.output {
+                Destination::Stdout => Box::new(Stdout::new(args.write_buffer)?),
+                // signals are caught by this process or forwarded to the
+                // command
+                Destination::Unix(path) => Box::new(Unixsock::new(path, || {
+                    signal::received().is_some() || child::terminated()
+                })),
+                Destination::Udp(addr) => {
+                    if !args.meta.is_empty() || args.json_meta {
+                        eprintln!("udp output: notification meta data is not sent");
+                    }
+                    let network = Network::new(addr)?;
+                    Box::new(match args.security_level {
+                        SecurityLevel::None => network,
+                        level => network.security(level, credentials(args)?),
+                    })
+                }
+            },
+            limiter: args.limiter.build(
+                args.limit,
+                Duration::from_secs(args.window),
+                args.burst.unwrap_or(args.limit),
+            ),
+            fragmenter: Fragmenter::new(args.max_event_length, args.max_event_id),
+            discarded: Discarded::new(),
+            dedup: Dedup::new(),
+            json_keys: JsonKeys {
+                message: args.json_message_key.clone(),
+                severity: args.json_severity_key.clone(),
+                time: args.json_time_key.clone(),
+                host: args.json_host_key.clone(),
+                meta: args.json_meta,
+            },
+            sources: sources(args),
+            multiline: multiline(args),
+            pending: HashMap::new(),
+            state,
+            stats: Stats::new(),
+            metrics: args.metrics.iter().cloned().map(Metric::new).collect(),
+            next_interval: (args.stats || !args.metrics.is_empty())
+                .then(|| Instant::now() + Duration::from_secs_f64(args.interval)),
+        })
+    }
+
+    fn line(&mut self, mut line: Line) -> Result<(), Box<dyn std::error::Error>> {
+        self.stats.lines += 1;
+        self.stats.bytes += line.text.len() as u64;
+
+        line.chomp();
 
-        let buflen = match stdin.read_line(&mut buf) {
-            Ok(0) => return Ok(()),
-            Ok(n) => n,
-            Err(err) => return Err(Box::new(err)),
+        let multiline = match &self.multiline {
+            Some(multiline) => self
+                .pending
+                .entry((line.source, line.address.clone()))
+                .or_insert_with(|| multiline.clone()),
+            None => return self.record(&line),
         };
 
-        let len = match buf.find('\0') {
-            Some(n) => n,
-            None => buflen - if buf.ends_with('\n') { 1 } else { 0 },
+        match multiline.push(line, Instant::now()) {
+            Some(line) => self.record(&line),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, line: &Line) -> Result<(), Box<dyn std::error::Error>> {
+        if let (Some(state), Some(position)) = (&mut self.state, line.position) {
+            state.set(&self.args.follow[line.source].path, position);
+        }
+
+        let buf = &line.text;
+
+        for metric in &mut self.metrics {
+            metric.observe(buf);
+        }
+
+        let mut record = match self.args.input_format {
+            InputFormat::Raw => None,
+            InputFormat::Syslog => syslog::parse(buf),
+            InputFormat::Json => json::parse(buf, &self.json_keys),
+            InputFormat::Regex => self
+                .args
+                .input_regex
+                .as_ref()
+                .and_then(|regex| pattern::parse(buf, regex)),
+        }
+        .unwrap_or_else(|| Record::new(buf));
+
+        if let Some(source) = self.sources.get(line.source) {
+            record.plugin_instance = source.plugin_instance.clone();
+            record.type_instance = source.type_instance.clone();
+            record.severity = record.severity.or(source.severity);
+        }
+
+        if let Some(address) = &line.address {
+            record
+                .meta
+                .push(Meta::new("source", MetaValue::String(address.clone())));
+        }
+
+        if !filter::selected(&self.args.include, &self.args.exclude, &record.message) {
+            self.stats.filtered += 1;
+            if self.args.verbose {
+                let _ = writeln!(io::stderr(), "FILTER:{}:{}", self.stats.filtered, buf);
+            }
+            return Ok(());
+        }
+
+        // severity rules and filters match the message before rewriting
+        record.severity =
+            severity::classify(&self.args.severity_rules, &record.message).or(record.severity);
+
+        for rewrite in &self.args.rewrites {
+            if let Cow::Owned(message) = rewrite.apply(&record.message) {
+                record.message = message;
+            }
+        }
+
+        let now = Instant::now();
+
+        if self.args.dedup > 0 {
+            if self.dedup.repeated(&record, now) {
+                return Ok(());
+            }
+            self.repeats()?;
+        }
+
+        let total = self.fragmenter.count(&record.message);
+
+        if self.args.limit > 0 && !self.limiter.allow(total, now) {
+            if self.args.verbose {
+                let _ = writeln!(
+                    io::stderr(),
+                    "DISCARD:{}/{}:{}",
+                    self.limiter.count(),
+                    self.args.limit,
+                    buf
+                );
+            }
+            self.discarded.add(&record.message, total, now);
+            self.stats.discarded_messages += 1;
+            self.stats.discarded_fragments += total as u64;
+            return Ok(());
+        }
+
+        self.notify(&record)
+    }
+
+    fn notify(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+        let service = &self.args.service;
+
+        let host = match &record.host {
+            Some(host) if self.args.input_host && host.len() < HOSTNAME_MAX_LEN => host,
+            _ => &self.args.hostname,
         };
 
-        let t1 = Instant::now();
+        let time = record.time.unwrap_or_else(notification::now);
+        let severity = record.severity.unwrap_or(self.args.severity);
+
+        let plugin_instance = record
+            .plugin_instance
+            .as_ref()
+            .unwrap_or(&service.plugin_instance);
+        let type_instance = record
+            .type_instance
+            .as_ref()
+            .unwrap_or(&service.type_instance);
+
+        for fragment in self.fragmenter.fragment(&record.message) {
+            let notification = Notification {
+                host: host.clone(),
+                time,
+                severity,
+                plugin: service.plugin.clone(),
+                plugin_instance: plugin_instance.clone(),
+                ctype: service.ctype.clone(),
+                type_instance: type_instance.clone(),
+                message: fragment,
+                meta: self.args.meta.iter().chain(&record.meta).cloned().collect(),
+            };
 
-        if t1.duration_since(t0).as_secs() >= args.window {
-            count = 0;
-            t0 = t1;
+            let result = self.output.notify(&notification);
+            self.check(result)?;
+            self.stats.fragments += 1;
         }
 
-        let chunks = len / args.max_event_length;
-        let rem = len % args.max_event_length;
-        let total = chunks + if rem == 0 { 0 } else { 1 };
+        Ok(())
+    }
 
-        count += total;
+    fn putval(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let service = &self.args.service;
 
-        if args.limit > 0 && count > args.limit {
-            if args.verbose {
-                eprint!("DISCARD:{}/{}:{}", count, args.limit, buf);
+        let plugin_instance = if service.plugin_instance.is_empty() {
+            service.plugin.clone()
+        } else {
+            format!("{}-{}", service.plugin, service.plugin_instance)
+        };
+
+        let template = ValueList {
+            host: self.args.hostname.clone(),
+            time: notification::now(),
+            interval: self.args.interval,
+            plugin: "prv".to_string(),
+            plugin_instance,
+            ..Default::default()
+        };
+
+        if self.args.stats {
+            self.stats.dropped = self.output.dropped();
+            for vl in self.stats.values(&template, self.limiter.count()) {
+                let result = self.output.putval(&vl);
+                self.check(result)?;
             }
-            continue;
         }
 
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        for i in 0..self.metrics.len() {
+            let value = match self.metrics[i].take() {
+                Some(value) => value,
+                None => continue,
+            };
+            let rule = &self.metrics[i].rule;
+            let vl = ValueList {
+                plugin: service.plugin.clone(),
+                plugin_instance: service.plugin_instance.clone(),
+                ctype: rule.ctype.clone(),
+                type_instance: rule.name.clone(),
+                values: vec![value],
+                ..template.clone()
+            };
+            let result = self.output.putval(&vl);
+            self.check(result)?;
+        }
 
-        let mut start = 0;
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        [
+            self.discarded
+                .deadline(Duration::from_secs(self.args.window)),
+            self.dedup.deadline(Duration::from_secs(self.args.dedup)),
+            self.next_interval,
+        ]
+        .into_iter()
+        .chain(self.pending.values().map(Multiline::deadline))
+        .flatten()
+        .min()
+    }
+
+    fn tick(&mut self, now: Instant) -> Result<(), Box<dyn std::error::Error>> {
+        let expired: Vec<Line> = self
+            .pending
+            .values_mut()
+            .filter(|m| m.deadline().is_some_and(|t| t <= now))
+            .filter_map(Multiline::flush)
+            .collect();
+        for line in expired {
+            self.record(&line)?;
+        }
+
+        // datagram source addresses are not reused
+        self.pending.retain(|_, m| m.deadline().is_some());
+
+        if self
+            .discarded
+            .deadline(Duration::from_secs(self.args.window))
+            .is_some_and(|t| t <= now)
+        {
+            self.summarize()?;
+        }
 
-        for n in 0..total {
-            stdout.write_all(
-                format!(
-                    "PUTNOTIF host={} severity=okay time={} plugin={} type={} message=\"",
-                    args.hostname,
-                    now.as_secs(),
-                    plugin,
-                    ctype,
-                )
-                .as_bytes(),
-            )?;
-            if total > 1 {
-                stdout.write_all(format!("@{}:{}:{}@", id, n + 1, total).as_bytes())?;
+        if self
+            .dedup
+            .deadline(Duration::from_secs(self.args.dedup))
+            .is_some_and(|t| t <= now)
+        {
+            self.repeats()?;
+        }
+
+        if let Some(t) = self.next_interval.filter(|&t| t <= now) {
+            self.putval()?;
+            self.next_interval = Some(t + Duration::from_secs_f64(self.args.interval));
+        }
+
+        Ok(())
+    }
+
+    // send the pending multi-line records at the end of input
+    fn drain(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let pending: Vec<Line> = self
+            .pending
+            .values_mut()
+            .filter_map(Multiline::flush)
+            .collect();
+        for line in pending {
+            self.record(&line)?;
+        }
+        Ok(())
+    }
+
+    fn repeats(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.dedup.take() {
+            Some(summary) => self.notify(&summary),
+            None => Ok(()),
+        }
+    }
+
+    fn summarize(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.discarded.take() {
+            Some(summary) => self.notify(&Record {
+                severity: Some(Severity::Warning),
+                ..Record::new(summary)
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // the command exit is a failure unless the exit status is 0
+    fn exited(
+        &mut self,
+        status: ExitStatus,
+        action: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let severity = if status.success() {
+            Severity::Okay
+        } else {
+            Severity::Failure
+        };
+
+        let mut message = child::describe(&self.args.exec[0], status);
+        if !action.is_empty() {
+            message.push_str(": ");
+            message.push_str(action);
+        }
+
+        self.notify(&Record {
+            severity: Some(severity),
+            ..Record::new(message)
+        })
+    }
+
+    // the command could not be run
+    fn spawn_failed(
+        &mut self,
+        err: &io::Error,
+        action: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut message = child::describe_error(&self.args.exec[0], err);
+        if !action.is_empty() {
+            message.push_str(": ");
+            message.push_str(action);
+        }
+
+        self.notify(&Record {
+            severity: Some(Severity::Failure),
+            ..Record::new(message)
+        })
+    }
+
+    // wait until `t`, sending the discard summaries and statistics when due
+    fn sleep_until(&mut self, t: Instant) -> Result<(), Box<dyn std::error::Error>> {
+        self.output.flush()?;
+        loop {
+            let now = Instant::now();
+            if now >= t {
+                return Ok(());
             }
-            let mut eol = false;
-            let remainder = len - start;
-            let end = if remainder > args.max_event_length {
-                start + args.max_event_length
-            } else {
-                len
+            let next = self.deadline().map_or(t, |d| d.min(t));
+            thread::sleep(next.saturating_duration_since(now));
+            self.tick(Instant::now())?;
+            self.output.flush()?;
+        }
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.drain()?;
+        self.repeats()?;
+        self.summarize()?;
+        self.output.flush()?;
+        self.checkpoint()?;
+        report_dropped(self.output.as_ref());
+        Ok(())
+    }
+
+    // save the followed file positions after the output is flushed
+    fn checkpoint(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            Some(state) => state.save(),
+            None => Ok(()),
+        }
+    }
+
+    // report commands rejected by collectd
+    fn check(&self, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                let _ = writeln!(io::stderr(), "REJECTED:{}", err);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+fn credentials(args: &Args) -> io::Result<Credentials> {
+    let path = args.auth_file.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--security-level requires --auth-file",
+        )
+    })?;
+    Credentials::from_file(path, args.username.as_deref())
+}
+
+fn recv_until<T>(
+    rx: &mpsc::Receiver<T>,
+    deadline: Option<Instant>,
+) -> Result<T, mpsc::RecvTimeoutError> {
+    match deadline {
+        Some(t) => rx.recv_timeout(t.saturating_duration_since(Instant::now())),
+        None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+    }
+}
+
+// notification fields of the input sources: stdin, the command stdout and
+// stderr or the followed files, in order
+fn sources(args: &Args) -> Vec<Source> {
+    if !args.exec.is_empty() {
+        return vec![
+            Source::default(),
+            Source {
+                severity: Some(args.stderr_severity),
+                type_instance: Some(args.stderr_type_instance.clone()),
+                ..Default::default()
+            },
+        ];
+    }
+
+    args.follow
+        .iter()
+        .map(|f| match args.follow_instance {
+            Instance::Plugin => Source {
+                plugin_instance: Some(f.instance.clone()),
+                ..Default::default()
+            },
+            Instance::Type => Source {
+                type_instance: Some(f.instance.clone()),
+                ..Default::default()
+            },
+        })
+        .collect()
+}
+
+fn multiline(args: &Args) -> Option<Multiline> {
+    let mode = match (&args.multiline_continue, &args.multiline_start) {
+        (Some(regex), _) => Mode::Continue(regex.clone()),
+        (None, Some(regex)) => Mode::Start(regex.clone()),
+        (None, None) => return None,
+    };
+
+    Some(Multiline::new(
+        mode,
+        &args.multiline_separator,
+        Duration::from_secs_f64(args.multiline_timeout),
+        args.multiline_max_lines,
+    ))
+}
+
+fn read_lines() -> Lines {
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+    read_from(io::stdin(), 0, tx);
+    rx
+}
+
+fn read_from<R: Read + Send + 'static>(
+    reader: R,
+    source: usize,
+    tx: mpsc::SyncSender<io::Result<Line>>,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let mut buf = String::new();
+            let line = match reader.read_line(&mut buf) {
+                Ok(0) => return,
+                Ok(_) => Ok(Line {
+                    text: buf,
+                    source,
+                    ..Default::default()
+                }),
+                Err(err) => Err(err),
             };
-            for c in buf[start..end].bytes() {
-                match c as char {
-                    '\\' => stdout.write_all(b"\\\\"),
-                    '"' => stdout.write_all(b"\\\""),
-                    '\r' | '\n' => {
-                        eol = true;
-                        Ok(())
+            if tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// the command stdout and stderr are read until both are closed
+fn exec(args: &Args) -> io::Result<(Child, Lines)> {
+    let mut child = child::spawn(&args.exec)?;
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+
+    read_from(child.stdout.take().expect("piped stdout"), 0, tx.clone());
+    read_from(child.stderr.take().expect("piped stderr"), 1, tx);
+
+    Ok((child, rx))
+}
+
+// the command is restarted after the delay: if the command cannot be run,
+// restarting is retried with backoff until the restart limit is reached
+fn respawn(
+    args: &Args,
+    prv: &mut Prv,
+    restart: &mut Restart,
+    mut delay: Duration,
+) -> Result<Result<(Child, Lines), i32>, Box<dyn std::error::Error>> {
+    loop {
+        prv.sleep_until(Instant::now() + delay)?;
+
+        let started = Instant::now();
+        let err = match exec(args) {
+            Ok(spawned) => return Ok(Ok(spawned)),
+            Err(err) => err,
+        };
+
+        match restart.next(started, Instant::now()) {
+            Some(next) => {
+                prv.spawn_failed(&err, &format!("restarting in {}s", next.as_secs_f64()))?;
+                delay = next;
+            }
+            None => {
+                prv.spawn_failed(&err, "restart limit reached")?;
+                return Ok(Err(child::spawn_exit_code(&err)));
+            }
+        }
+    }
+}
+
+// followed files are read until exit: read errors are reported and retried
+fn follow(args: &Args, state: Option<&State>) -> Lines {
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+
+    for (source, f) in args.follow.iter().enumerate() {
+        let tx = tx.clone();
+
+        let start = match state.and_then(|state| state.get(&f.path)) {
+            Some((ino, offset)) => Start::Resume { ino, offset },
+            None => args.follow_start,
+        };
+
+        let mut follower = Follower::new(&f.path, start);
+        if let Some(max_backlog) = args.max_backlog {
+            follower = follower.max_backlog(max_backlog);
+        }
+
+        thread::spawn(move || loop {
+            match follower.read_line() {
+                Ok(Some(text)) => {
+                    let line = Line {
+                        text,
+                        source,
+                        position: Some(follower.position()),
+                        ..Default::default()
+                    };
+                    if tx.send(Ok(line)).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => thread::sleep(FOLLOW_INTERVAL),
+                Err(err) => {
+                    // stdout may be set non-blocking while stderr is written
+                    let _ = writeln!(io::stderr(), "{}: {}", follower.path().display(), err);
+                    thread::sleep(FOLLOW_INTERVAL);
+                }
+            }
+        });
+    }
+
+    rx
+}
+
+// each datagram is a line: receive errors are reported and retried
+fn listen(args: &Args) -> io::Result<Lines> {
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+
+    let listeners = args
+        .listen
+        .iter()
+        .map(Listen::bind)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    for (source, listener) in listeners.into_iter().enumerate() {
+        let tx = tx.clone();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; listen::DATAGRAM_MAX_LEN];
+            loop {
+                match listener.recv(&mut buf) {
+                    Ok((n, address)) => {
+                        let line = Line {
+                            text: String::from_utf8_lossy(&buf[..n]).into_owned(),
+                            source,
+                            address,
+                            ..Default::default()
+                        };
+                        if tx.send(Ok(line)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = writeln!(io::stderr(), "listen: {}", err);
+                        thread::sleep(LISTEN_RETRY_INTERVAL);
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(rx)
+}
+
+fn reassemble(
+    args: &Args,
+    expire: u64,
+    state_file: Option<&Path>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut stdout = Stdout::new(args.write_buffer)?;
+    let lines = read_lines();
+    signal::catch(&signal::EXIT_SIGNALS);
+
+    let mut decoder = Decoder::new();
+    let mut reassembler = Reassembler::new(Duration::from_secs(expire));
+
+    // NotificationExec commands may run concurrently
+    let _lock = state_file.map(lock).transpose()?;
+
+    if let Some(path) = state_file {
+        reassembler.load(path, Instant::now())?;
+    }
+
+    // incomplete messages are saved or reported lost on exit
+    let close = |reassembler: &mut Reassembler, stdout: &Stdout| -> io::Result<()> {
+        match state_file {
+            Some(path) => {
+                let now = Instant::now();
+                reassembler.expire(now).iter().for_each(report_lost);
+                reassembler.save(path, now)?;
+            }
+            None => reassembler.flush().iter().for_each(report_lost),
+        }
+        report_dropped(stdout);
+        Ok(())
+    };
+
+    loop {
+        let line = match lines.recv_timeout(SIGNAL_INTERVAL) {
+            Ok(line) => Some(line?),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                close(&mut reassembler, &stdout)?;
+                return Ok(0);
+            }
+        };
+
+        if let Some(line) = line {
+            match decoder.decode(&line.text) {
+                Decoded::Notification(n) => {
+                    if let Some(n) = reassembler.push(n, Instant::now()) {
+                        stdout.write_line(&n.to_string())?;
                     }
-                    _ => stdout.write_all(&[c]),
-                }?;
-                if eol {
-                    break;
                 }
+                Decoded::Line(line) => stdout.write_line(&line)?,
+                Decoded::Pending => (),
             }
-            stdout.write_all(b"\"\n")?;
-            stdout.flush()?;
+        }
+
+        reassembler
+            .expire(Instant::now())
+            .iter()
+            .for_each(report_lost);
+
+        if let Some(sig) = signal::received() {
+            close(&mut reassembler, &stdout)?;
+            return Ok(128 + sig);
+        }
+    }
+}
+
+// lock the state file until the lock file is closed
+fn lock(path: &Path) -> io::Result<File> {
+    let mut lock = path.to_path_buf().into_os_string();
+    lock.push(".lock");
+
+    let file = OpenOptions::new().create(true).append(true).open(lock)?;
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(file);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
 
-            start = end;
+fn report_dropped(output: &dyn Output) {
+    if output.dropped() > 0 {
+        let _ = writeln!(io::stderr(), "DROPPED:{}", output.dropped());
+    }
+}
+
+fn report_lost(lost: &Lost) {
+    let n = &lost.notification;
+    eprintln!(
+        "LOST:{}/{}/{}:{}:{:?}:{}",
+        n.host, n.plugin, n.ctype, lost.id, lost.missing, n.message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // records the notification messages
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Output for Recorder {
+        fn notify(&mut self, n: &Notification) -> io::Result<()> {
+            self.0.borrow_mut().push(n.message.clone());
+            Ok(())
         }
 
-        if total > 1 {
-            id = (id % args.max_event_id) + 1;
+        fn putval(&mut self, _vl: &ValueList) -> io::Result<()> {
+            Ok(())
         }
     }
+
+    fn prv<'a>(args: &'a Args, sent: &Rc<RefCell<Vec<String>>>) -> Prv<'a> {
+        let mut prv = Prv::new(args, None).unwrap();
+        prv.output = Box::new(Recorder(Rc::clone(sent)));
+        prv
+    }
+
+    fn line(text: &str) -> Line {
+        Line {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filtered_lines_not_rate_limited() {
+        let args = Args::parse_from([
+            "collectd-prv",
+            "--hostname=host",
+            "--limit=2",
+            "--window=3600",
+            "--include=error",
+            "--exclude=ignore",
+        ]);
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let mut prv = prv(&args, &sent);
+
+        for text in ["debug 1\n", "error: ignore\n", "debug 2\n", "error: 1\n"] {
+            prv.line(line(text)).unwrap();
+        }
+        assert_eq!(prv.limiter.count(), 1);
+        assert_eq!(prv.stats.filtered, 3);
+
+        prv.line(line("error: 2\n")).unwrap();
+        prv.line(line("error: 3\n")).unwrap();
+        assert_eq!(*sent.borrow(), vec!["error: 1", "error: 2"]);
+        assert_eq!(prv.stats.discarded_messages, 1);
+    }
 }
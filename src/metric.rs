@@ -0,0 +1,206 @@
+use crate::value::Value;
+use crate::DATA_MAX_LEN;
+use regex::Regex;
+use std::str::FromStr;
+
+/// extracts values from lines matching a regular expression
+///
+/// Rules are written as `<name>:<type>:<regex>`. The name is the type
+/// instance. If the regex has a capture group, the value is the first
+/// captured number. Otherwise the value is the number of matching lines.
+#[derive(Clone, Debug)]
+pub struct MetricRule {
+    pub name: String,
+    pub ctype: String,
+    pub regex: Regex,
+}
+
+impl FromStr for MetricRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(3, ':');
+        let (name, ctype, regex) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(ctype), Some(regex)) => (name, ctype, regex),
+            _ => return Err(format!("invalid metric: {}", s)),
+        };
+
+        if name.len() >= DATA_MAX_LEN
+            || ctype.is_empty()
+            || ctype.len() >= DATA_MAX_LEN
+            || ctype.contains('-')
+            || name.contains('/')
+            || ctype.contains('/')
+        {
+            return Err(format!("invalid metric: {}", s));
+        }
+
+        Ok(MetricRule {
+            name: name.to_string(),
+            ctype: ctype.to_string(),
+            regex: Regex::new(regex).map_err(|err| err.to_string())?,
+        })
+    }
+}
+
+/// metric state for an interval
+///
+/// Captured values are reported as the last value seen in the interval.
+/// Match counts are reported per interval for `gauge` and `absolute`
+/// types and as a running total for `derive` and `counter` types.
+#[derive(Clone, Debug)]
+pub struct Metric {
+    pub rule: MetricRule,
+    value: Option<f64>,
+    count: u64,
+    total: u64,
+}
+
+impl Metric {
+    pub fn new(rule: MetricRule) -> Self {
+        Metric {
+            rule,
+            value: None,
+            count: 0,
+            total: 0,
+        }
+    }
+
+    pub fn observe(&mut self, line: &str) {
+        let caps = match self.rule.regex.captures(line) {
+            Some(caps) => caps,
+            None => return,
+        };
+
+        self.count += 1;
+        self.total += 1;
+
+        if let Some(v) = caps.get(1).and_then(|m| m.as_str().trim().parse().ok()) {
+            self.value = Some(v);
+        }
+    }
+
+    /// value for the interval
+    pub fn take(&mut self) -> Option<Value> {
+        let count = std::mem::take(&mut self.count);
+        let value = self.value.take();
+
+        if self.rule.regex.captures_len() > 1 {
+            let v = value?;
+            return Some(match self.rule.ctype.as_str() {
+                "derive" => Value::Derive(v as i64),
+                "counter" => Value::Counter(v as u64),
+                "absolute" => Value::Absolute(v as u64),
+                _ => Value::Gauge(v),
+            });
+        }
+
+        Some(match self.rule.ctype.as_str() {
+            "derive" => Value::Derive(self.total as i64),
+            "counter" => Value::Counter(self.total),
+            "absolute" => Value::Absolute(count),
+            _ => Value::Gauge(count as f64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(rule: &str) -> Metric {
+        Metric::new(rule.parse().unwrap())
+    }
+
+    fn observe(m: &mut Metric, lines: &[&str]) -> Option<Value> {
+        for line in lines {
+            m.observe(line);
+        }
+        m.take()
+    }
+
+    #[test]
+    fn parse() {
+        let rule: MetricRule = "errors:derive:error: (\\d+)".parse().unwrap();
+        assert_eq!(rule.name, "errors");
+        assert_eq!(rule.ctype, "derive");
+        assert_eq!(rule.regex.as_str(), "error: (\\d+)");
+
+        // the regex may contain `:`
+        let rule: MetricRule = "t:gauge:a:b".parse().unwrap();
+        assert_eq!(rule.regex.as_str(), "a:b");
+
+        // the name may be empty
+        assert!(":gauge:x".parse::<MetricRule>().is_ok());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let long = "x".repeat(DATA_MAX_LEN);
+        for s in [
+            "errors",
+            "errors:gauge",
+            "errors::x",
+            "errors:my-type:x",
+            "a/b:gauge:x",
+            "errors:a/b:x",
+            "errors:gauge:(",
+            &format!("{}:gauge:x", long),
+            &format!("errors:{}:x", long),
+        ] {
+            assert!(s.parse::<MetricRule>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn count_per_interval() {
+        let mut m = metric("errors:gauge:error");
+        assert_eq!(
+            observe(&mut m, &["error", "ok", "error"]),
+            Some(Value::Gauge(2.0))
+        );
+        assert_eq!(observe(&mut m, &["error"]), Some(Value::Gauge(1.0)));
+        assert_eq!(observe(&mut m, &[]), Some(Value::Gauge(0.0)));
+
+        let mut m = metric("errors:absolute:error");
+        assert_eq!(
+            observe(&mut m, &["error", "error"]),
+            Some(Value::Absolute(2))
+        );
+        assert_eq!(observe(&mut m, &[]), Some(Value::Absolute(0)));
+    }
+
+    #[test]
+    fn count_total() {
+        let mut m = metric("errors:derive:error");
+        assert_eq!(observe(&mut m, &["error", "error"]), Some(Value::Derive(2)));
+        assert_eq!(observe(&mut m, &["error"]), Some(Value::Derive(3)));
+        assert_eq!(observe(&mut m, &[]), Some(Value::Derive(3)));
+
+        let mut m = metric("errors:counter:error");
+        assert_eq!(observe(&mut m, &["error"]), Some(Value::Counter(1)));
+        assert_eq!(observe(&mut m, &["error"]), Some(Value::Counter(2)));
+    }
+
+    #[test]
+    fn captured() {
+        let mut m = metric("latency:gauge:latency=([0-9.]+)ms");
+
+        // the last value in the interval
+        assert_eq!(
+            observe(&mut m, &["latency=1.5ms", "latency=2.5ms", "other"]),
+            Some(Value::Gauge(2.5))
+        );
+
+        // no value without a captured number
+        assert_eq!(observe(&mut m, &[]), None);
+        assert_eq!(observe(&mut m, &["latency=xms"]), None);
+
+        let mut m = metric("bytes:derive:sent ([0-9]+)");
+        assert_eq!(observe(&mut m, &["sent 42"]), Some(Value::Derive(42)));
+        let mut m = metric("bytes:counter:sent ([0-9]+)");
+        assert_eq!(observe(&mut m, &["sent 42"]), Some(Value::Counter(42)));
+        let mut m = metric("bytes:absolute:sent ([0-9]+)");
+        assert_eq!(observe(&mut m, &["sent 42"]), Some(Value::Absolute(42)));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::input::Line;
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+/// multi-line record boundary
+#[derive(Clone, Debug)]
+pub enum Mode {
+    /// lines matching the regex are appended to the previous line
+    Continue(Regex),
+    /// lines matching the regex begin a record
+    Start(Regex),
+}
+
+/// joins multi-line records, such as stack traces, into a single line
+///
+/// A record is complete when the next record begins, when no line has been
+/// added for the flush timeout or when the record reaches the max number of
+/// lines. Lines are joined with the separator.
+#[derive(Clone, Debug)]
+pub struct Multiline {
+    mode: Mode,
+    separator: String,
+    timeout: Duration,
+    max_lines: usize,
+    pending: Option<Line>,
+    lines: usize,
+    t0: Instant,
+}
+
+impl Multiline {
+    pub fn new(mode: Mode, separator: &str, timeout: Duration, max_lines: usize) -> Self {
+        Multiline {
+            mode,
+            separator: separator.to_string(),
+            timeout,
+            max_lines: max_lines.max(1),
+            pending: None,
+            lines: 0,
+            t0: Instant::now(),
+        }
+    }
+
+    /// add a line without the trailing newline, returning the previous
+    /// record if complete
+    pub fn push(&mut self, line: Line, now: Instant) -> Option<Line> {
+        let continued = match &self.mode {
+            Mode::Continue(regex) => regex.is_match(&line.text),
+            Mode::Start(regex) => !regex.is_match(&line.text),
+        };
+
+        let complete = match &mut self.pending {
+            Some(pending) if continued && self.lines < self.max_lines => {
+                pending.text.push_str(&self.separator);
+                pending.text.push_str(&line.text);
+                pending.position = line.position;
+                self.lines += 1;
+                self.t0 = now;
+                return None;
+            }
+            _ => self.pending.take(),
+        };
+
+        self.pending = Some(line);
+        self.lines = 1;
+        self.t0 = now;
+
+        complete
+    }
+
+    /// flush timeout of the pending record
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|_| self.t0 + self.timeout)
+    }
+
+    /// remove the pending record
+    pub fn flush(&mut self) -> Option<Line> {
+        self.lines = 0;
+        self.pending.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> Line {
+        Line {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn join(m: &mut Multiline, lines: &[&str]) -> Vec<String> {
+        let now = Instant::now();
+        let mut records: Vec<String> = lines
+            .iter()
+            .filter_map(|text| m.push(line(text), now))
+            .map(|l| l.text)
+            .collect();
+        records.extend(m.flush().map(|l| l.text));
+        records
+    }
+
+    #[test]
+    fn continue_mode() {
+        let regex = Regex::new(r"^\s").unwrap();
+        let mut m = Multiline::new(Mode::Continue(regex), " | ", Duration::from_secs(1), 500);
+
+        assert_eq!(
+            join(
+                &mut m,
+                &["error: x", "  at a", "  at b", "next", "  at c", "last"]
+            ),
+            vec!["error: x |   at a |   at b", "next |   at c", "last"]
+        );
+
+        // a continuation line without a previous line begins a record
+        assert_eq!(join(&mut m, &["  at a", "  at b"]), vec!["  at a |   at b"]);
+    }
+
+    #[test]
+    fn crlf() {
+        let regex = Regex::new(r"^\s").unwrap();
+        let mut m = Multiline::new(Mode::Continue(regex), " | ", Duration::from_secs(1), 500);
+
+        let lines: Vec<String> = ["err\r\n", "  at a\r\n", "  at b\r\n", "next\r"]
+            .iter()
+            .map(|text| {
+                let mut line = line(text);
+                line.chomp();
+                line.text
+            })
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+        assert_eq!(join(&mut m, &lines), vec!["err |   at a |   at b", "next"]);
+    }
+
+    #[test]
+    fn start_mode() {
+        let regex = Regex::new(r"^\d{4}-").unwrap();
+        let mut m = Multiline::new(Mode::Start(regex), " ", Duration::from_secs(1), 500);
+
+        assert_eq!(
+            join(
+                &mut m,
+                &["2024-01-01 error", "trace 1", "trace 2", "2024-01-02 ok"]
+            ),
+            vec!["2024-01-01 error trace 1 trace 2", "2024-01-02 ok"]
+        );
+    }
+
+    #[test]
+    fn max_lines() {
+        let regex = Regex::new(r"^\s").unwrap();
+        let mut m = Multiline::new(Mode::Continue(regex), "", Duration::from_secs(1), 3);
+
+        assert_eq!(
+            join(&mut m, &["a", " b", " c", " d", " e", " f", " g", "h"]),
+            vec!["a b c", " d e f", " g", "h"]
+        );
+
+        // a max of 0 lines is 1 line
+        let regex = Regex::new(r"^\s").unwrap();
+        let mut m = Multiline::new(Mode::Continue(regex), "", Duration::from_secs(1), 0);
+        assert_eq!(join(&mut m, &["a", " b"]), vec!["a", " b"]);
+    }
+
+    #[test]
+    fn deadline() {
+        let regex = Regex::new(r"^\s").unwrap();
+        let timeout = Duration::from_secs(1);
+        let mut m = Multiline::new(Mode::Continue(regex), " ", timeout, 500);
+        let t0 = Instant::now();
+
+        assert_eq!(m.deadline(), None);
+        assert_eq!(m.push(line("a"), t0), None);
+        assert_eq!(m.deadline(), Some(t0 + timeout));
+
+        // the timeout restarts when a line is added
+        let t1 = t0 + Duration::from_millis(500);
+        assert_eq!(m.push(line(" b"), t1), None);
+        assert_eq!(m.deadline(), Some(t1 + timeout));
+
+        assert_eq!(m.flush().map(|l| l.text), Some("a  b".to_string()));
+        assert_eq!(m.deadline(), None);
+        assert_eq!(m.flush(), None);
+    }
+
+    #[test]
+    fn position() {
+        let regex = Regex::new(r"^\s").unwrap();
+        let mut m = Multiline::new(Mode::Continue(regex), " ", Duration::from_secs(1), 500);
+        let now = Instant::now();
+
+        let at = |text: &str, offset| Line {
+            position: Some((1, offset)),
+            ..line(text)
+        };
+
+        assert_eq!(m.push(at("a", 2), now), None);
+        assert_eq!(m.push(at(" b", 5), now), None);
+
+        // the record position follows the last line
+        let record = m.push(at("c", 7), now).unwrap();
+        assert_eq!(record.text, "a  b");
+        assert_eq!(record.position, Some((1, 5)));
+    }
+}
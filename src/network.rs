@@ -0,0 +1,669 @@
+use crate::notification::{Notification, Severity};
+use crate::output::Output;
+use crate::value::{Value, ValueList};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::str::FromStr;
+
+/// default collectd network packet size
+pub const PACKET_MAX_LEN: usize = 1452;
+
+const TYPE_HOST: u16 = 0x0000;
+const TYPE_TIME: u16 = 0x0001;
+const TYPE_PLUGIN: u16 = 0x0002;
+const TYPE_PLUGIN_INSTANCE: u16 = 0x0003;
+const TYPE_TYPE: u16 = 0x0004;
+const TYPE_TYPE_INSTANCE: u16 = 0x0005;
+const TYPE_VALUES: u16 = 0x0006;
+const TYPE_MESSAGE: u16 = 0x0100;
+const TYPE_SEVERITY: u16 = 0x0101;
+const TYPE_INTERVAL_HR: u16 = 0x0009;
+const TYPE_SIGN_SHA256: u16 = 0x0200;
+const TYPE_ENCR_AES256: u16 = 0x0210;
+
+// header, HMAC
+const PART_SIGNATURE_SHA256_SIZE: usize = 4 + 32;
+// header, username length, IV, SHA-1 checksum
+const PART_ENCRYPTION_AES256_SIZE: usize = 4 + 2 + 16 + 20;
+
+const DS_TYPE_COUNTER: u8 = 0;
+const DS_TYPE_GAUGE: u8 = 1;
+const DS_TYPE_DERIVE: u8 = 2;
+const DS_TYPE_ABSOLUTE: u8 = 3;
+
+/// encode a notification as collectd binary protocol parts
+///
+/// Instance parts are always included: collectd keeps the values of
+/// previous parts in the packet. Meta data is not encoded: the network
+/// protocol does not support notification meta data. Strings are truncated
+/// to the max part length.
+pub fn encode_notification(n: &Notification) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_string(&mut buf, TYPE_HOST, &n.host);
+    put_number(&mut buf, TYPE_TIME, n.time);
+    put_string(&mut buf, TYPE_PLUGIN, &n.plugin);
+    put_string(&mut buf, TYPE_PLUGIN_INSTANCE, &n.plugin_instance);
+    put_string(&mut buf, TYPE_TYPE, &n.ctype);
+    put_string(&mut buf, TYPE_TYPE_INSTANCE, &n.type_instance);
+    put_number(
+        &mut buf,
+        TYPE_SEVERITY,
+        match n.severity {
+            Severity::Failure => 1,
+            Severity::Warning => 2,
+            Severity::Okay => 4,
+        },
+    );
+    // the notification is dispatched by the message part
+    put_string(&mut buf, TYPE_MESSAGE, &n.message);
+    buf
+}
+
+/// encode a value list as collectd binary protocol parts
+pub fn encode_values(vl: &ValueList) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_string(&mut buf, TYPE_HOST, &vl.host);
+    put_number(&mut buf, TYPE_TIME, vl.time);
+    // high resolution time: 2^-30 seconds
+    put_number(
+        &mut buf,
+        TYPE_INTERVAL_HR,
+        (vl.interval * (1u64 << 30) as f64) as u64,
+    );
+    put_string(&mut buf, TYPE_PLUGIN, &vl.plugin);
+    put_string(&mut buf, TYPE_PLUGIN_INSTANCE, &vl.plugin_instance);
+    put_string(&mut buf, TYPE_TYPE, &vl.ctype);
+    put_string(&mut buf, TYPE_TYPE_INSTANCE, &vl.type_instance);
+
+    let n = vl.values.len();
+    put_header(&mut buf, TYPE_VALUES, 6 + 9 * n);
+    buf.extend_from_slice(&(n as u16).to_be_bytes());
+    for v in &vl.values {
+        buf.push(match v {
+            Value::Counter(_) => DS_TYPE_COUNTER,
+            Value::Gauge(_) => DS_TYPE_GAUGE,
+            Value::Derive(_) => DS_TYPE_DERIVE,
+            Value::Absolute(_) => DS_TYPE_ABSOLUTE,
+        });
+    }
+    for v in &vl.values {
+        match v {
+            // gauges are little endian
+            Value::Gauge(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Derive(v) => buf.extend_from_slice(&v.to_be_bytes()),
+            Value::Counter(v) | Value::Absolute(v) => buf.extend_from_slice(&v.to_be_bytes()),
+        }
+    }
+    buf
+}
+
+fn put_header(buf: &mut Vec<u8>, ptype: u16, len: usize) {
+    buf.extend_from_slice(&ptype.to_be_bytes());
+    buf.extend_from_slice(&(len as u16).to_be_bytes());
+}
+
+// header, NUL
+const PART_STRING_MAX_LEN: usize = u16::MAX as usize - 4 - 1;
+
+fn put_string(buf: &mut Vec<u8>, ptype: u16, s: &str) {
+    let s = truncate(s, PART_STRING_MAX_LEN);
+    put_header(buf, ptype, 4 + s.len() + 1);
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+fn truncate(s: &str, max_len: usize) -> &str {
+    let mut end = s.len().min(max_len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn put_number(buf: &mut Vec<u8>, ptype: u16, n: u64) {
+    put_header(buf, ptype, 12);
+    buf.extend_from_slice(&n.to_be_bytes());
+}
+
+/// network plugin security level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityLevel {
+    None,
+    Sign,
+    Encrypt,
+}
+
+impl FromStr for SecurityLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SecurityLevel::None),
+            "sign" => Ok(SecurityLevel::Sign),
+            "encrypt" => Ok(SecurityLevel::Encrypt),
+            _ => Err(format!("invalid security level: {}", s)),
+        }
+    }
+}
+
+/// max length of the network plugin username
+pub const USERNAME_MAX_LEN: usize = 255;
+
+/// network plugin credentials
+///
+/// The username must not be longer than `USERNAME_MAX_LEN`.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// read credentials from a collectd `AuthFile`
+    ///
+    /// Each line of the file is `<username>: <password>`. If no username
+    /// is given, the first entry is used.
+    pub fn from_file(path: &Path, username: Option<&str>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+
+        let credentials = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once(':'))
+            .map(|(user, password)| Credentials {
+                username: user.trim().to_string(),
+                password: password.trim().to_string(),
+            })
+            .find(|c| username.is_none_or(|u| u == c.username))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{}: no credentials for user", path.display()),
+                )
+            })?;
+
+        if credentials.username.is_empty() || credentials.username.len() > USERNAME_MAX_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: invalid username length", path.display()),
+            ));
+        }
+
+        Ok(credentials)
+    }
+}
+
+/// sign a packet: the HMAC-SHA-256 of the username and payload
+pub fn sign(payload: &[u8], credentials: &Credentials) -> Vec<u8> {
+    let username = credentials.username.as_bytes();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(credentials.password.as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(username);
+    mac.update(payload);
+
+    let mut buf = Vec::with_capacity(PART_SIGNATURE_SHA256_SIZE + username.len() + payload.len());
+    put_header(
+        &mut buf,
+        TYPE_SIGN_SHA256,
+        PART_SIGNATURE_SHA256_SIZE + username.len(),
+    );
+    buf.extend_from_slice(&mac.finalize().into_bytes());
+    buf.extend_from_slice(username);
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// encrypt a packet: AES-256-OFB with the SHA-256 of the password as the
+/// key, the payload is prefixed by its SHA-1 checksum
+pub fn encrypt(payload: &[u8], credentials: &Credentials) -> io::Result<Vec<u8>> {
+    let mut iv = [0u8; 16];
+    getrandom::getrandom(&mut iv).map_err(io::Error::from)?;
+    Ok(encrypt_iv(payload, credentials, iv))
+}
+
+fn encrypt_iv(payload: &[u8], credentials: &Credentials, iv: [u8; 16]) -> Vec<u8> {
+    let username = credentials.username.as_bytes();
+
+    let key = Sha256::digest(credentials.password.as_bytes());
+
+    let mut data = Vec::with_capacity(20 + payload.len());
+    data.extend_from_slice(&Sha1::digest(payload));
+    data.extend_from_slice(payload);
+
+    ofb::Ofb::<aes::Aes256>::new(&key, &iv.into()).apply_keystream(&mut data);
+
+    let len = PART_ENCRYPTION_AES256_SIZE + username.len() + payload.len();
+    let mut buf = Vec::with_capacity(len);
+    put_header(&mut buf, TYPE_ENCR_AES256, len);
+    buf.extend_from_slice(&(username.len() as u16).to_be_bytes());
+    buf.extend_from_slice(username);
+    buf.extend_from_slice(&iv);
+    buf.extend_from_slice(&data);
+    buf
+}
+
+/// collectd network plugin output over UDP
+///
+/// Notifications and values are batched into packets of up to
+/// `PACKET_MAX_LEN` bytes, including the signature or encryption
+/// overhead. Buffered packets are sent on flush.
+///
+/// Commands in packets that could not be sent because the destination is
+/// unreachable, e.g. collectd is restarting, are counted as dropped.
+#[derive(Debug)]
+pub struct Network {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+    commands: u64,
+    dropped: u64,
+    max_len: usize,
+    security: SecurityLevel,
+    credentials: Option<Credentials>,
+}
+
+impl Network {
+    /// connect to `<host>:<port>`
+    pub fn new(addr: &str) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{}: no address", addr))
+        })?;
+
+        let socket = UdpSocket::bind(if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.connect(addr)?;
+
+        Ok(Network {
+            socket,
+            buf: Vec::with_capacity(PACKET_MAX_LEN),
+            commands: 0,
+            dropped: 0,
+            max_len: PACKET_MAX_LEN,
+            security: SecurityLevel::None,
+            credentials: None,
+        })
+    }
+
+    /// sign or encrypt packets
+    pub fn security(mut self, security: SecurityLevel, credentials: Credentials) -> Self {
+        let username = credentials.username.len().min(USERNAME_MAX_LEN);
+        self.max_len = match security {
+            SecurityLevel::None => PACKET_MAX_LEN,
+            SecurityLevel::Sign => PACKET_MAX_LEN - PART_SIGNATURE_SHA256_SIZE - username,
+            SecurityLevel::Encrypt => PACKET_MAX_LEN - PART_ENCRYPTION_AES256_SIZE - username,
+        };
+        self.security = security;
+        self.credentials = Some(credentials);
+        self
+    }
+
+    fn push(&mut self, parts: Vec<u8>) -> io::Result<()> {
+        if !self.buf.is_empty() && self.buf.len() + parts.len() > self.max_len {
+            self.flush()?;
+        }
+        self.buf.extend_from_slice(&parts);
+        self.commands += 1;
+        Ok(())
+    }
+}
+
+// send errors caused by the state of the destination or the network
+fn transient(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::ConnectionRefused
+        || matches!(
+            err.raw_os_error(),
+            Some(
+                libc::ENOBUFS
+                    | libc::EHOSTUNREACH
+                    | libc::ENETUNREACH
+                    | libc::EHOSTDOWN
+                    | libc::ENETDOWN
+            )
+        )
+}
+
+impl Output for Network {
+    // messages are truncated to fit in a packet
+    fn notify(&mut self, n: &Notification) -> io::Result<()> {
+        let parts = encode_notification(n);
+        if parts.len() <= self.max_len {
+            return self.push(parts);
+        }
+
+        let excess = parts.len() - self.max_len;
+        let mut n = n.clone();
+        let len = truncate(&n.message, n.message.len().saturating_sub(excess)).len();
+        n.message.truncate(len);
+        self.push(encode_notification(&n))
+    }
+
+    fn putval(&mut self, vl: &ValueList) -> io::Result<()> {
+        self.push(encode_values(vl))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let packet = match (self.security, &self.credentials) {
+            (SecurityLevel::Sign, Some(c)) => sign(&self.buf, c),
+            (SecurityLevel::Encrypt, Some(c)) => encrypt(&self.buf, c)?,
+            _ => std::mem::take(&mut self.buf),
+        };
+        self.buf.clear();
+
+        let commands = std::mem::take(&mut self.commands);
+        match self.socket.send(&packet) {
+            Ok(_) => Ok(()),
+            Err(err) if transient(&err) => {
+                self.dropped += commands;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn listen() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        (socket, addr)
+    }
+
+    fn recv(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = vec![0; 65535];
+        let n = socket.recv(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    // split a packet into (type, body) parts
+    fn parts(mut packet: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        let mut parts = Vec::new();
+        while !packet.is_empty() {
+            let ptype = u16::from_be_bytes([packet[0], packet[1]]);
+            let len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+            assert!(len >= 4 && len <= packet.len());
+            parts.push((ptype, packet[4..len].to_vec()));
+            packet = &packet[len..];
+        }
+        parts
+    }
+
+    fn string(body: &[u8]) -> &str {
+        let (nul, s) = body.split_last().unwrap();
+        assert_eq!(*nul, 0);
+        std::str::from_utf8(s).unwrap()
+    }
+
+    fn number(body: &[u8]) -> u64 {
+        u64::from_be_bytes(body.try_into().unwrap())
+    }
+
+    fn notification(severity: Severity, message: &str) -> Notification {
+        Notification::builder()
+            .host("host")
+            .time(1234)
+            .severity(severity)
+            .plugin("plugin")
+            .plugin_instance("pi")
+            .ctype("type")
+            .message(message)
+            .build()
+    }
+
+    #[test]
+    fn notifications() {
+        let (socket, addr) = listen();
+        let mut network = Network::new(&addr).unwrap();
+
+        network
+            .notify(&notification(Severity::Failure, "failure"))
+            .unwrap();
+        network
+            .notify(&notification(Severity::Warning, "warning"))
+            .unwrap();
+        network
+            .notify(&notification(Severity::Okay, "okay"))
+            .unwrap();
+        network.flush().unwrap();
+
+        let parts = parts(&recv(&socket));
+        assert_eq!(parts.len(), 24);
+
+        let first = &parts[..8];
+        assert_eq!(first[0].0, TYPE_HOST);
+        assert_eq!(string(&first[0].1), "host");
+        assert_eq!(first[1].0, TYPE_TIME);
+        assert_eq!(number(&first[1].1), 1234);
+        assert_eq!(first[2].0, TYPE_PLUGIN);
+        assert_eq!(string(&first[2].1), "plugin");
+        assert_eq!(first[3].0, TYPE_PLUGIN_INSTANCE);
+        assert_eq!(string(&first[3].1), "pi");
+        assert_eq!(first[4].0, TYPE_TYPE);
+        assert_eq!(string(&first[4].1), "type");
+        assert_eq!(first[5].0, TYPE_TYPE_INSTANCE);
+        assert_eq!(string(&first[5].1), "");
+        assert_eq!(first[6].0, TYPE_SEVERITY);
+        assert_eq!(first[7].0, TYPE_MESSAGE);
+        assert_eq!(string(&first[7].1), "failure");
+
+        let severities: Vec<u64> = parts
+            .iter()
+            .filter(|(ptype, _)| *ptype == TYPE_SEVERITY)
+            .map(|(_, body)| number(body))
+            .collect();
+        assert_eq!(severities, vec![1, 2, 4]);
+
+        let messages: Vec<&str> = parts
+            .iter()
+            .filter(|(ptype, _)| *ptype == TYPE_MESSAGE)
+            .map(|(_, body)| string(body))
+            .collect();
+        assert_eq!(messages, vec!["failure", "warning", "okay"]);
+    }
+
+    #[test]
+    fn values() {
+        let (socket, addr) = listen();
+        let mut network = Network::new(&addr).unwrap();
+
+        let vl = ValueList {
+            host: "host".to_string(),
+            time: 1234,
+            interval: 10.0,
+            plugin: "plugin".to_string(),
+            ctype: "gauge".to_string(),
+            values: vec![Value::Gauge(1.5), Value::Derive(-2)],
+            ..Default::default()
+        };
+        network.putval(&vl).unwrap();
+        network.flush().unwrap();
+
+        let parts = parts(&recv(&socket));
+        assert_eq!(parts[2].0, TYPE_INTERVAL_HR);
+        assert_eq!(number(&parts[2].1), 10 << 30);
+
+        let (ptype, body) = parts.last().unwrap();
+        assert_eq!(*ptype, TYPE_VALUES);
+        assert_eq!(body.len(), 2 + 2 * 9);
+        assert_eq!(&body[..4], &[0, 2, DS_TYPE_GAUGE, DS_TYPE_DERIVE]);
+        assert_eq!(&body[4..12], &1.5f64.to_le_bytes());
+        assert_eq!(&body[12..20], &(-2i64).to_be_bytes());
+    }
+
+    #[test]
+    fn batching() {
+        let (socket, addr) = listen();
+        let mut network = Network::new(&addr).unwrap();
+
+        let message = "x".repeat(100);
+        let count = 50;
+        for _ in 0..count {
+            network
+                .notify(&notification(Severity::Okay, &message))
+                .unwrap();
+        }
+        network.flush().unwrap();
+
+        let mut received = 0;
+        let mut packets = 0;
+        while received < count {
+            let packet = recv(&socket);
+            assert!(packet.len() <= PACKET_MAX_LEN);
+            received += parts(&packet)
+                .iter()
+                .filter(|(ptype, _)| *ptype == TYPE_MESSAGE)
+                .count();
+            packets += 1;
+        }
+
+        assert_eq!(received, count);
+        let len = encode_notification(&notification(Severity::Okay, &message)).len();
+        assert_eq!(packets, count.div_ceil(PACKET_MAX_LEN / len));
+    }
+
+    #[test]
+    fn oversize_message() {
+        let (socket, addr) = listen();
+        let mut network = Network::new(&addr).unwrap();
+
+        let message = "é".repeat(5000);
+        network
+            .notify(&notification(Severity::Okay, &message))
+            .unwrap();
+        network.flush().unwrap();
+
+        let packet = recv(&socket);
+        assert!(packet.len() <= PACKET_MAX_LEN);
+        assert!(packet.len() >= PACKET_MAX_LEN - 1);
+
+        let parts = parts(&packet);
+        let (_, body) = parts.last().unwrap();
+        assert!(message.starts_with(string(body)));
+    }
+
+    #[test]
+    fn part_max_len() {
+        let n = notification(Severity::Okay, &"x".repeat(70000));
+        let parts = parts(&encode_notification(&n));
+        let (ptype, body) = parts.last().unwrap();
+        assert_eq!(*ptype, TYPE_MESSAGE);
+        assert_eq!(string(body).len(), PART_STRING_MAX_LEN);
+    }
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn signature() {
+        let packet = sign(b"payload", &credentials());
+        let parts = parts(&packet[..4 + 32 + 4]);
+        assert_eq!(parts[0].0, TYPE_SIGN_SHA256);
+
+        // HMAC-SHA-256(key = "secret", "user" + "payload")
+        assert_eq!(
+            hex(&parts[0].1[..32]),
+            "c9f1b2e2b3fde1dab23ec8b1a70df88fceac086380d8f8d10bfbc64a98cbb82e"
+        );
+        assert_eq!(&parts[0].1[32..], b"user");
+        assert_eq!(&packet[40..], b"payload");
+    }
+
+    #[test]
+    fn encryption_known_answer() {
+        let iv: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let packet = encrypt_iv(b"payload", &credentials(), iv);
+
+        assert_eq!(&packet[..2], &TYPE_ENCR_AES256.to_be_bytes());
+        assert_eq!(
+            u16::from_be_bytes([packet[2], packet[3]]) as usize,
+            packet.len()
+        );
+        assert_eq!(&packet[4..6], &[0, 4]);
+        assert_eq!(&packet[6..10], b"user");
+        assert_eq!(&packet[10..26], &iv);
+
+        // AES-256-OFB(key = SHA-256("secret"), SHA-1("payload") + "payload")
+        assert_eq!(
+            hex(&packet[26..]),
+            "d17bf2b49eab14cd5a991833404c0b42b16c341ff03bb05f046653"
+        );
+    }
+
+    #[test]
+    fn encryption() {
+        let packet = encrypt(b"payload", &credentials()).unwrap();
+        let iv: [u8; 16] = packet[10..26].try_into().unwrap();
+
+        let key = Sha256::digest(b"secret");
+        let mut data = packet[26..].to_vec();
+        ofb::Ofb::<aes::Aes256>::new(&key, &iv.into()).apply_keystream(&mut data);
+
+        assert_eq!(&data[..20], Sha1::digest(b"payload").as_slice());
+        assert_eq!(&data[20..], b"payload");
+    }
+
+    #[test]
+    fn username_length() {
+        let path = std::env::temp_dir().join(format!("collectd-prv-auth-{}", std::process::id()));
+
+        fs::write(
+            &path,
+            format!("{}: secret\n", "u".repeat(USERNAME_MAX_LEN + 1)),
+        )
+        .unwrap();
+        let err = Credentials::from_file(&path, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(&path, "# comment\nother: x\nuser: secret\n").unwrap();
+        let c = Credentials::from_file(&path, Some("user")).unwrap();
+        assert_eq!(c.password, "secret");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn connection_refused() {
+        let (socket, addr) = listen();
+        drop(socket);
+
+        let mut network = Network::new(&addr).unwrap();
+        for _ in 0..3 {
+            network.notify(&notification(Severity::Okay, "x")).unwrap();
+            network.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(network.dropped() > 0);
+    }
+}
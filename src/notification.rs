@@ -0,0 +1,519 @@
+use crate::DATA_MAX_LEN;
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// notification severity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Severity {
+    Failure,
+    Warning,
+    #[default]
+    Okay,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Failure => "failure",
+            Severity::Warning => "warning",
+            Severity::Okay => "okay",
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "failure" => Ok(Severity::Failure),
+            "warning" => Ok(Severity::Warning),
+            "okay" => Ok(Severity::Okay),
+            _ => Err(format!("invalid severity: {}", s)),
+        }
+    }
+}
+
+/// typed notification meta data value
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetaValue {
+    String(String),
+    Int(i64),
+    UInt(u64),
+    Double(f64),
+    Bool(bool),
+}
+
+/// notification meta data entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meta {
+    pub key: String,
+    pub value: MetaValue,
+}
+
+impl Meta {
+    pub fn new(key: impl Into<String>, value: MetaValue) -> Self {
+        Meta {
+            key: key.into(),
+            value,
+        }
+    }
+
+    /// meta data keys are written unquoted
+    pub fn is_valid_key(key: &str) -> bool {
+        !key.is_empty()
+            && key.len() < DATA_MAX_LEN
+            && !key.contains(|c: char| c.is_whitespace() || c == '=' || c == '"' || c == '\\')
+    }
+}
+
+impl FromStr for Meta {
+    type Err = String;
+
+    /// parse `[s:]<key>=<value>`
+    ///
+    /// The collectd PUTNOTIF command accepts only string meta data: other
+    /// type prefixes are rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid meta data: no `=` found in `{}`", s))?;
+
+        let meta = if key.len() > 2 && key.as_bytes()[1] == b':' {
+            match parse_meta(key, value)? {
+                meta @ Meta {
+                    value: MetaValue::String(_),
+                    ..
+                } => meta,
+                _ => return Err(format!("unsupported meta data type: {}", key)),
+            }
+        } else {
+            Meta::new(key, MetaValue::String(value.to_string()))
+        };
+
+        if !Meta::is_valid_key(&meta.key) {
+            return Err(format!("invalid meta data key: {}", meta.key));
+        }
+
+        Ok(meta)
+    }
+}
+
+impl fmt::Display for Meta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            MetaValue::String(v) => {
+                write!(f, "s:{}=", self.key)?;
+                write_quoted(f, v)
+            }
+            MetaValue::Int(v) => write!(f, "i:{}={}", self.key, v),
+            MetaValue::UInt(v) => write!(f, "u:{}={}", self.key, v),
+            MetaValue::Double(v) => write!(f, "d:{}={}", self.key, v),
+            MetaValue::Bool(v) => write!(f, "b:{}={}", self.key, v),
+        }
+    }
+}
+
+/// collectd notification
+///
+/// Formatting a notification produces an exec plugin PUTNOTIF command
+/// without the trailing newline.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notification {
+    pub host: String,
+    pub time: u64,
+    pub severity: Severity,
+    pub plugin: String,
+    pub plugin_instance: String,
+    pub ctype: String,
+    pub type_instance: String,
+    pub message: String,
+    pub meta: Vec<Meta>,
+}
+
+impl Notification {
+    pub fn builder() -> NotificationBuilder {
+        NotificationBuilder::default()
+    }
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PUTNOTIF host=")?;
+        write_value(f, &self.host)?;
+        write!(f, " severity={} time={} plugin=", self.severity, self.time)?;
+        write_value(f, &self.plugin)?;
+        if !self.plugin_instance.is_empty() {
+            f.write_str(" plugin_instance=")?;
+            write_value(f, &self.plugin_instance)?;
+        }
+        f.write_str(" type=")?;
+        write_value(f, &self.ctype)?;
+        if !self.type_instance.is_empty() {
+            f.write_str(" type_instance=")?;
+            write_value(f, &self.type_instance)?;
+        }
+        for m in &self.meta {
+            write!(f, " {}", m)?;
+        }
+        f.write_str(" message=")?;
+        write_quoted(f, &self.message)
+    }
+}
+
+impl FromStr for Notification {
+    type Err = String;
+
+    /// parse a PUTNOTIF command
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end_matches(['\r', '\n']);
+        let mut rest = s
+            .strip_prefix("PUTNOTIF")
+            .filter(|r| r.is_empty() || r.starts_with(char::is_whitespace))
+            .ok_or_else(|| format!("not a PUTNOTIF command: {}", s))?;
+
+        let mut n = Notification::default();
+
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+
+            let (key, value, tail) = parse_option(rest)?;
+            rest = tail;
+
+            match key.to_ascii_lowercase().as_str() {
+                "host" => n.host = value,
+                "severity" => n.severity = value.parse()?,
+                "time" => {
+                    n.time = value
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid time: {}", value))?
+                        as u64
+                }
+                "plugin" => n.plugin = value,
+                "plugin_instance" => n.plugin_instance = value,
+                "type" => n.ctype = value,
+                "type_instance" => n.type_instance = value,
+                "message" => n.message = value,
+                _ => n.meta.push(parse_meta(key, &value)?),
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+fn parse_meta(key: &str, value: &str) -> Result<Meta, String> {
+    let invalid = || format!("invalid meta data: {}={}", key, value);
+
+    let (kind, name) = key.split_once(':').ok_or_else(invalid)?;
+    let value = match kind.to_ascii_lowercase().as_str() {
+        "s" => MetaValue::String(value.to_string()),
+        "i" => MetaValue::Int(value.parse().map_err(|_| invalid())?),
+        "u" => MetaValue::UInt(value.parse().map_err(|_| invalid())?),
+        "d" => MetaValue::Double(value.parse().map_err(|_| invalid())?),
+        "b" => MetaValue::Bool(value.parse().map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Meta::new(name, value))
+}
+
+// parse a `key=value` option, returning the key, the unquoted value and the
+// remaining input
+fn parse_option(s: &str) -> Result<(&str, String, &str), String> {
+    let pos = s
+        .find('=')
+        .filter(|&pos| pos > 0 && !s[..pos].contains(char::is_whitespace))
+        .ok_or_else(|| format!("invalid option: {}", s))?;
+
+    let key = &s[..pos];
+    let s = &s[pos + 1..];
+
+    let mut value = String::new();
+
+    match s.strip_prefix('"') {
+        Some(quoted) => {
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => return Ok((key, value, &quoted[i + 1..])),
+                    '\\' => match chars.next() {
+                        Some((_, c)) => value.push(c),
+                        None => break,
+                    },
+                    _ => value.push(c),
+                }
+            }
+            Err(format!("unterminated string: {}", key))
+        }
+        None => {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            value.push_str(&s[..end]);
+            Ok((key, value, &s[end..]))
+        }
+    }
+}
+
+/// builder for notifications
+///
+/// The notification time defaults to the current time.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationBuilder {
+    n: Notification,
+}
+
+impl NotificationBuilder {
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.n.host = host.into();
+        self
+    }
+
+    pub fn time(mut self, time: u64) -> Self {
+        self.n.time = time;
+        self
+    }
+
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.n.severity = severity;
+        self
+    }
+
+    pub fn plugin(mut self, plugin: impl Into<String>) -> Self {
+        self.n.plugin = plugin.into();
+        self
+    }
+
+    pub fn plugin_instance(mut self, plugin_instance: impl Into<String>) -> Self {
+        self.n.plugin_instance = plugin_instance.into();
+        self
+    }
+
+    pub fn ctype(mut self, ctype: impl Into<String>) -> Self {
+        self.n.ctype = ctype.into();
+        self
+    }
+
+    pub fn type_instance(mut self, type_instance: impl Into<String>) -> Self {
+        self.n.type_instance = type_instance.into();
+        self
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.n.message = message.into();
+        self
+    }
+
+    pub fn meta(mut self, meta: Meta) -> Self {
+        self.n.meta.push(meta);
+        self
+    }
+
+    pub fn build(self) -> Notification {
+        let mut n = self.n;
+        if n.time == 0 {
+            n.time = now();
+        }
+        n
+    }
+}
+
+/// seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub(crate) fn write_value(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    if s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        write_quoted(f, s)
+    } else {
+        f.write_str(s)
+    }
+}
+
+// quoted strings end at the first line break: the exec plugin reads one
+// command per line
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '\\' => f.write_str("\\\\"),
+            '"' => f.write_str("\\\""),
+            '\r' | '\n' => break,
+            _ => f.write_char(c),
+        }?;
+    }
+    f.write_str("\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification() -> Notification {
+        Notification::builder()
+            .host("host")
+            .time(1700000000)
+            .severity(Severity::Warning)
+            .plugin("stdout")
+            .ctype("prv")
+            .message("message")
+            .build()
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            notification().to_string(),
+            "PUTNOTIF host=host severity=warning time=1700000000 plugin=stdout type=prv \
+             message=\"message\""
+        );
+
+        let n = Notification {
+            plugin_instance: "a".to_string(),
+            type_instance: "b".to_string(),
+            ..notification()
+        };
+        assert_eq!(
+            n.to_string(),
+            "PUTNOTIF host=host severity=warning time=1700000000 plugin=stdout \
+             plugin_instance=a type=prv type_instance=b message=\"message\""
+        );
+    }
+
+    #[test]
+    fn display_quoting() {
+        let n = Notification {
+            host: "my host".to_string(),
+            plugin: "a\"b".to_string(),
+            ctype: "".to_string(),
+            message: "say \"hi\" c:\\dir".to_string(),
+            ..notification()
+        };
+        assert_eq!(
+            n.to_string(),
+            "PUTNOTIF host=\"my host\" severity=warning time=1700000000 plugin=\"a\\\"b\" \
+             type=\"\" message=\"say \\\"hi\\\" c:\\\\dir\""
+        );
+    }
+
+    #[test]
+    fn display_line_break() {
+        let n = Notification {
+            message: "first\nsecond".to_string(),
+            ..notification()
+        };
+        assert!(n.to_string().ends_with(" message=\"first\""));
+
+        let n = Notification {
+            message: "first\r\nsecond".to_string(),
+            ..notification()
+        };
+        assert!(n.to_string().ends_with(" message=\"first\""));
+    }
+
+    #[test]
+    fn display_meta() {
+        let n = Notification {
+            meta: vec![
+                Meta::new("s", MetaValue::String("a \"b\"".to_string())),
+                Meta::new("i", MetaValue::Int(-1)),
+                Meta::new("u", MetaValue::UInt(2)),
+                Meta::new("d", MetaValue::Double(1.5)),
+                Meta::new("b", MetaValue::Bool(true)),
+            ],
+            ..notification()
+        };
+        assert_eq!(
+            n.to_string(),
+            "PUTNOTIF host=host severity=warning time=1700000000 plugin=stdout type=prv \
+             s:s=\"a \\\"b\\\"\" i:i=-1 u:u=2 d:d=1.5 b:b=true message=\"message\""
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let n = Notification {
+            host: "my host".to_string(),
+            plugin_instance: "a b".to_string(),
+            type_instance: "\\".to_string(),
+            message: "say \"hi\" c:\\dir".to_string(),
+            meta: vec![
+                Meta::new("s", MetaValue::String("a \"b\"".to_string())),
+                Meta::new("i", MetaValue::Int(-1)),
+                Meta::new("u", MetaValue::UInt(2)),
+                Meta::new("d", MetaValue::Double(1.5)),
+                Meta::new("b", MetaValue::Bool(false)),
+            ],
+            ..notification()
+        };
+        assert_eq!(n.to_string().parse(), Ok(n));
+        assert_eq!(notification().to_string().parse(), Ok(notification()));
+    }
+
+    #[test]
+    fn parse() {
+        let n: Notification = "PUTNOTIF message=\"a b\" severity=FAILURE time=1.5 host=h\n"
+            .parse()
+            .unwrap();
+        assert_eq!(n.message, "a b");
+        assert_eq!(n.severity, Severity::Failure);
+        assert_eq!(n.time, 1);
+        assert_eq!(n.host, "h");
+
+        assert!("PUTVAL x".parse::<Notification>().is_err());
+        assert!("PUTNOTIFY message=x".parse::<Notification>().is_err());
+        assert!("PUTNOTIF message=\"x".parse::<Notification>().is_err());
+        assert!("PUTNOTIF message".parse::<Notification>().is_err());
+        assert!("PUTNOTIF severity=bad".parse::<Notification>().is_err());
+        assert!("PUTNOTIF x:key=1".parse::<Notification>().is_err());
+        assert!("PUTNOTIF i:key=a".parse::<Notification>().is_err());
+    }
+
+    #[test]
+    fn option() {
+        assert_eq!(parse_option("a=b c"), Ok(("a", "b".to_string(), " c")));
+        assert_eq!(
+            parse_option("a=\"b \\\" \\\\ c\" d"),
+            Ok(("a", "b \" \\ c".to_string(), " d"))
+        );
+        assert_eq!(parse_option("a="), Ok(("a", "".to_string(), "")));
+        assert!(parse_option("=b").is_err());
+        assert!(parse_option("a b=c").is_err());
+        assert!(parse_option("a=\"b\\").is_err());
+    }
+
+    #[test]
+    fn meta() {
+        assert_eq!(
+            "key=1.0".parse(),
+            Ok(Meta::new("key", MetaValue::String("1.0".to_string())))
+        );
+        assert_eq!(
+            "s:key=a=b".parse(),
+            Ok(Meta::new("key", MetaValue::String("a=b".to_string())))
+        );
+        assert_eq!(
+            "S:key=1".parse(),
+            Ok(Meta::new("key", MetaValue::String("1".to_string())))
+        );
+
+        // collectd accepts only string meta data
+        assert!("i:key=-7".parse::<Meta>().is_err());
+        assert!("u:key=7".parse::<Meta>().is_err());
+        assert!("d:key=2".parse::<Meta>().is_err());
+        assert!("b:key=true".parse::<Meta>().is_err());
+
+        assert!("key".parse::<Meta>().is_err());
+        assert!("x:key=1".parse::<Meta>().is_err());
+        assert!("my key=1".parse::<Meta>().is_err());
+        assert!("=1".parse::<Meta>().is_err());
+    }
+}
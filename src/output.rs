@@ -0,0 +1,208 @@
+use crate::notification::Notification;
+use crate::value::ValueList;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// notification and value sink
+///
+/// Commands rejected by collectd return an `io::ErrorKind::InvalidData`
+/// error containing the response.
+pub trait Output {
+    fn notify(&mut self, n: &Notification) -> io::Result<()>;
+
+    fn putval(&mut self, vl: &ValueList) -> io::Result<()>;
+
+    /// send buffered commands
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// number of commands discarded by the output
+    fn dropped(&self) -> u64 {
+        0
+    }
+}
+
+/// output destination: `stdout`, `unix:<path>` or `udp:<host>:<port>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Stdout,
+    Unix(PathBuf),
+    Udp(String),
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s == "stdout" => Ok(Destination::Stdout),
+            Some(("unix", path)) if !path.is_empty() => Ok(Destination::Unix(PathBuf::from(path))),
+            Some(("udp", addr)) if !addr.is_empty() => Ok(Destination::Udp(addr.to_string())),
+            _ => Err(format!("invalid output: {}", s)),
+        }
+    }
+}
+
+/// behaviour if the write buffer is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteBuffer {
+    /// return an error
+    Exit,
+    /// discard the line
+    Drop,
+    /// wait until the line is written
+    Block,
+}
+
+impl FromStr for WriteBuffer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exit" => Ok(WriteBuffer::Exit),
+            "drop" => Ok(WriteBuffer::Drop),
+            "block" => Ok(WriteBuffer::Block),
+            _ => Err(format!("invalid write buffer policy: {}", s)),
+        }
+    }
+}
+
+/// line writer for stdout
+///
+/// For the `exit` and `drop` policies, stdout is set non-blocking while a
+/// line is written. A line is either written completely or, if the write
+/// buffer is full, not at all: with `exit`, the write returns an
+/// `io::ErrorKind::WouldBlock` error.
+///
+/// The non-blocking flag applies to the open file shared with the other
+/// descriptors of the pipe or terminal, such as stderr: the flag is
+/// restored after each write.
+#[derive(Debug)]
+pub struct Stdout {
+    policy: WriteBuffer,
+    dropped: u64,
+}
+
+impl Stdout {
+    pub fn new(policy: WriteBuffer) -> io::Result<Self> {
+        if policy != WriteBuffer::Block {
+            // check stdout supports the non-blocking flag
+            drop(NonBlocking::set(libc::STDOUT_FILENO)?);
+        }
+        Ok(Stdout { policy, dropped: 0 })
+    }
+
+    /// number of lines discarded by the `drop` policy
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(line.len() + 1);
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+
+        if self.policy == WriteBuffer::Block {
+            let mut stdout = io::stdout();
+            stdout.write_all(&buf)?;
+            return stdout.flush();
+        }
+
+        match write_nonblocking(libc::STDOUT_FILENO, &buf) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => match self.policy {
+                WriteBuffer::Drop => {
+                    self.dropped += 1;
+                    Ok(())
+                }
+                _ => Err(err),
+            },
+            result => result,
+        }
+    }
+}
+
+impl Output for Stdout {
+    fn notify(&mut self, n: &Notification) -> io::Result<()> {
+        self.write_line(&n.to_string())
+    }
+
+    fn putval(&mut self, vl: &ValueList) -> io::Result<()> {
+        self.write_line(&vl.to_string())
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+// sets a file descriptor non-blocking until dropped
+struct NonBlocking {
+    fd: libc::c_int,
+    flags: libc::c_int,
+}
+
+impl NonBlocking {
+    fn set(fd: libc::c_int) -> io::Result<Self> {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(NonBlocking { fd, flags })
+    }
+}
+
+impl Drop for NonBlocking {
+    fn drop(&mut self) {
+        unsafe {
+            libc::fcntl(self.fd, libc::F_SETFL, self.flags);
+        }
+    }
+}
+
+// returns WouldBlock only if nothing was written: a partially written
+// line is completed
+fn write_nonblocking(fd: libc::c_int, buf: &[u8]) -> io::Result<()> {
+    let _nonblocking = NonBlocking::set(fd)?;
+    let mut written = 0;
+
+    while written < buf.len() {
+        let rest = &buf[written..];
+        let n = unsafe { libc::write(fd, rest.as_ptr() as *const libc::c_void, rest.len()) };
+        if n >= 0 {
+            written += n as usize;
+            continue;
+        }
+
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::Interrupted => (),
+            io::ErrorKind::WouldBlock if written > 0 => wait_writable(fd)?,
+            _ => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+fn wait_writable(fd: libc::c_int) -> io::Result<()> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    loop {
+        if unsafe { libc::poll(&mut pfd, 1, -1) } >= 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::input;
+use crate::input::Record;
+use crate::notification::{Meta, MetaValue};
+use regex::Regex;
+
+/// parse a line using the named capture groups of a regular expression
+///
+/// The `message`, `severity`, `time` and `host` groups set the notification
+/// fields. Other named groups are added as string meta data. If the
+/// `message` group is not defined, the message is the whole line.
+///
+/// Returns `None` if the line does not match.
+pub fn parse(line: &str, regex: &Regex) -> Option<Record> {
+    let caps = regex.captures(line)?;
+
+    let mut record = Record::new(caps.name("message").map_or(line, |m| m.as_str()));
+
+    for name in regex.capture_names().flatten() {
+        let value = match caps.name(name) {
+            Some(m) => m.as_str(),
+            None => continue,
+        };
+        match name {
+            "message" => (),
+            "severity" => record.severity = Some(input::level(value)),
+            "time" => record.time = input::timestamp(value),
+            "host" => record.host = Some(value.to_string()),
+            _ if Meta::is_valid_key(name) => record
+                .meta
+                .push(Meta::new(name, MetaValue::String(value.to_string()))),
+            _ => (),
+        }
+    }
+
+    Some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::Severity;
+
+    #[test]
+    fn fields() {
+        let regex = Regex::new(
+            r"^(?P<time>\S+) (?P<host>\S+) (?P<severity>\w+) \[(?P<component>\w+)\] (?P<message>.*)$",
+        )
+        .unwrap();
+        let r = parse("2003-10-11T22:14:15Z web1 ERROR [db] disk full", &regex).unwrap();
+        assert_eq!(r.message, "disk full");
+        assert_eq!(r.severity, Some(Severity::Failure));
+        assert_eq!(r.time, Some(1065910455));
+        assert_eq!(r.host.as_deref(), Some("web1"));
+        assert_eq!(
+            r.meta,
+            vec![Meta::new("component", MetaValue::String("db".to_string()))]
+        );
+    }
+
+    #[test]
+    fn whole_line() {
+        // without a message group, the message is the whole line
+        let regex = Regex::new(r"^(?P<severity>\w+):").unwrap();
+        let r = parse("warn: disk 90% full", &regex).unwrap();
+        assert_eq!(r.message, "warn: disk 90% full");
+        assert_eq!(r.severity, Some(Severity::Warning));
+    }
+
+    #[test]
+    fn unmatched_groups() {
+        let regex = Regex::new(r"^(?:(?P<host>\w+): )?(?P<message>.*)$").unwrap();
+        let r = parse("disk full", &regex).unwrap();
+        assert_eq!(r.message, "disk full");
+        assert_eq!(r.host, None);
+        assert!(r.meta.is_empty());
+    }
+
+    #[test]
+    fn no_match() {
+        let regex = Regex::new(r"^\d+ (?P<message>.*)$").unwrap();
+        assert!(parse("disk full", &regex).is_none());
+    }
+}
@@ -0,0 +1,476 @@
+use crate::fragment::Header;
+use crate::notification;
+use crate::notification::Notification;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// max fragments in a fragmented message: headers with a larger total are
+/// not fragment headers
+pub const MAX_TOTAL: usize = 1024;
+
+/// max incomplete fragmented messages: the oldest set is discarded
+pub const MAX_PENDING: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    host: String,
+    plugin: String,
+    plugin_instance: String,
+    ctype: String,
+    type_instance: String,
+    id: u64,
+}
+
+impl Key {
+    fn new(n: &Notification, id: u64) -> Self {
+        Key {
+            host: n.host.clone(),
+            plugin: n.plugin.clone(),
+            plugin_instance: n.plugin_instance.clone(),
+            ctype: n.ctype.clone(),
+            type_instance: n.type_instance.clone(),
+            id,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    notification: Notification,
+    fragments: Vec<Option<String>>,
+    received: usize,
+    t0: Instant,
+}
+
+impl Pending {
+    fn lost(self, id: u64) -> Lost {
+        let missing = self
+            .fragments
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.is_none())
+            .map(|(n, _)| n + 1)
+            .collect();
+        let mut notification = self.notification;
+        notification.message = self.fragments.into_iter().flatten().collect();
+        Lost {
+            notification,
+            id,
+            missing,
+        }
+    }
+}
+
+/// incomplete fragmented message
+///
+/// The notification message contains the fragments that were received.
+#[derive(Clone, Debug)]
+pub struct Lost {
+    pub notification: Notification,
+    pub id: u64,
+    pub missing: Vec<usize>,
+}
+
+/// rejoins `@id:n:total@` fragmented notifications
+///
+/// Fragments are grouped by host, plugin, type and fragment id. Sets that
+/// are not completed before the expiry timeout are discarded.
+///
+/// Notifications with a fragment total above `MAX_TOTAL` are returned
+/// unchanged.
+#[derive(Debug)]
+pub struct Reassembler {
+    expire: Duration,
+    pending: HashMap<Key, Pending>,
+    lost: Vec<Lost>,
+}
+
+impl Reassembler {
+    pub fn new(expire: Duration) -> Self {
+        Reassembler {
+            expire,
+            pending: HashMap::new(),
+            lost: Vec::new(),
+        }
+    }
+
+    /// add a notification, returning the message if complete
+    ///
+    /// Notifications without a fragment header are returned unchanged.
+    pub fn push(&mut self, mut n: Notification, now: Instant) -> Option<Notification> {
+        let (header, data) = match Header::parse(&n.message) {
+            Some((header, data)) if header.total <= MAX_TOTAL => (header, data.to_string()),
+            _ => return Some(n),
+        };
+
+        let key = Key::new(&n, header.id);
+
+        // the id has been reused before the previous set completed
+        if let Some(p) = self.pending.get(&key) {
+            if p.fragments.len() != header.total || p.fragments[header.n - 1].is_some() {
+                let p = self.pending.remove(&key).unwrap();
+                self.lost.push(p.lost(header.id));
+            }
+        }
+
+        if !self.pending.contains_key(&key) && self.pending.len() >= MAX_PENDING {
+            self.evict();
+        }
+
+        let p = self.pending.entry(key.clone()).or_insert_with(|| {
+            n.message.clear();
+            Pending {
+                notification: n,
+                fragments: vec![None; header.total],
+                received: 0,
+                t0: now,
+            }
+        });
+
+        p.fragments[header.n - 1] = Some(data);
+        p.received += 1;
+
+        if p.received < header.total {
+            return None;
+        }
+
+        let p = self.pending.remove(&key).unwrap();
+        let mut notification = p.notification;
+        notification.message = p.fragments.into_iter().flatten().collect();
+        Some(notification)
+    }
+
+    // discard the oldest incomplete set
+    fn evict(&mut self) {
+        let oldest = self
+            .pending
+            .iter()
+            .min_by_key(|(_, p)| p.t0)
+            .map(|(k, _)| k.clone());
+
+        if let Some(key) = oldest {
+            let p = self.pending.remove(&key).unwrap();
+            self.lost.push(p.lost(key.id));
+        }
+    }
+
+    /// remove incomplete sets older than the expiry timeout
+    pub fn expire(&mut self, now: Instant) -> Vec<Lost> {
+        let expired: Vec<Key> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.t0) >= self.expire)
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        for key in expired {
+            let p = self.pending.remove(&key).unwrap();
+            self.lost.push(p.lost(key.id));
+        }
+
+        std::mem::take(&mut self.lost)
+    }
+
+    /// remove all incomplete sets
+    pub fn flush(&mut self) -> Vec<Lost> {
+        for (key, p) in std::mem::take(&mut self.pending) {
+            self.lost.push(p.lost(key.id));
+        }
+        std::mem::take(&mut self.lost)
+    }
+
+    /// restore the incomplete sets written by `save`
+    ///
+    /// The sets keep the time elapsed since the first fragment was
+    /// received. A missing file is empty. Invalid lines are ignored.
+    pub fn load(&mut self, path: &Path, now: Instant) -> io::Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let epoch = notification::now();
+
+        let fragments = contents.lines().filter_map(|line| {
+            let (received, command) = line.split_once(' ')?;
+            let received: u64 = received.parse().ok()?;
+            let n: Notification = command.parse().ok()?;
+            Some((received, n))
+        });
+
+        for (received, n) in fragments {
+            let age = Duration::from_secs(epoch.saturating_sub(received));
+            self.push(n, now.checked_sub(age).unwrap_or(now));
+        }
+
+        Ok(())
+    }
+
+    /// write the fragments of the incomplete sets
+    ///
+    /// Collectd's NotificationExec runs a command for each notification:
+    /// the incomplete sets are saved between runs. The file contains a line
+    /// for each received fragment:
+    ///
+    /// ```text
+    /// <time of the first fragment of the set> <PUTNOTIF command>
+    /// ```
+    ///
+    /// The state is written to a temporary file and renamed over the file.
+    pub fn save(&self, path: &Path, now: Instant) -> io::Result<()> {
+        let mut tmp = path.to_path_buf().into_os_string();
+        tmp.push(".tmp");
+
+        let epoch = notification::now();
+
+        let mut file = fs::File::create(&tmp)?;
+        for (key, p) in &self.pending {
+            let received = epoch.saturating_sub(now.duration_since(p.t0).as_secs());
+            let total = p.fragments.len();
+            let fragments = p.fragments.iter().enumerate();
+            for (i, data) in fragments.filter_map(|(i, f)| Some((i, f.as_ref()?))) {
+                let mut n = p.notification.clone();
+                n.message = format!("@{}:{}:{}@{}", key.id, i + 1, total, data);
+                writeln!(file, "{} {}", received, n)?;
+            }
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// decoded input line
+#[derive(Clone, Debug)]
+pub enum Decoded {
+    Notification(Notification),
+    Line(String),
+    Pending,
+}
+
+/// decodes PUTNOTIF commands and NotificationExec notifications
+///
+/// NotificationExec notifications are a block of `Key: value` headers
+/// followed by an empty line and the message. Other lines are passed
+/// through.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    header: Option<Notification>,
+    body: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    pub fn decode(&mut self, line: &str) -> Decoded {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if let Some(mut n) = self.header.take() {
+            if self.body {
+                self.body = false;
+                n.message = line.to_string();
+                return Decoded::Notification(n);
+            }
+            if line.is_empty() {
+                self.body = true;
+                self.header = Some(n);
+                return Decoded::Pending;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                set_header(&mut n, key, value.trim());
+            }
+            self.header = Some(n);
+            return Decoded::Pending;
+        }
+
+        if line.starts_with("PUTNOTIF") {
+            if let Ok(n) = line.parse() {
+                return Decoded::Notification(n);
+            }
+        }
+
+        // NotificationExec notifications begin with the severity
+        if let Some(value) = line.strip_prefix("Severity:") {
+            let mut n = Notification::default();
+            if set_header(&mut n, "Severity", value.trim()) {
+                self.header = Some(n);
+                return Decoded::Pending;
+            }
+        }
+
+        Decoded::Line(line.to_string())
+    }
+}
+
+fn set_header(n: &mut Notification, key: &str, value: &str) -> bool {
+    match key {
+        "Severity" => match value.parse() {
+            Ok(severity) => n.severity = severity,
+            Err(_) => return false,
+        },
+        "Time" => match value.parse::<f64>() {
+            Ok(t) => n.time = t as u64,
+            Err(_) => return false,
+        },
+        "Host" => n.host = value.to_string(),
+        "Plugin" => n.plugin = value.to_string(),
+        "PluginInstance" => n.plugin_instance = value.to_string(),
+        "Type" => n.ctype = value.to_string(),
+        "TypeInstance" => n.type_instance = value.to_string(),
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(message: &str) -> Notification {
+        Notification::builder()
+            .host("h")
+            .plugin("p")
+            .ctype("t")
+            .message(message)
+            .build()
+    }
+
+    #[test]
+    fn reassemble() {
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert!(r.push(fragment("@1:2:2@world"), now).is_none());
+        let n = r.push(fragment("@1:1:2@hello "), now).unwrap();
+        assert_eq!(n.message, "hello world");
+        assert!(r.flush().is_empty());
+    }
+
+    #[test]
+    fn unfragmented() {
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let n = r.push(fragment("message"), Instant::now()).unwrap();
+        assert_eq!(n.message, "message");
+    }
+
+    #[test]
+    fn total_above_max() {
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let message = "@1:1:9999999999999@x";
+        let n = r.push(fragment(message), Instant::now()).unwrap();
+        assert_eq!(n.message, message);
+        assert!(r.flush().is_empty());
+    }
+
+    #[test]
+    fn max_pending() {
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let now = Instant::now();
+
+        for id in 0..=MAX_PENDING as u64 {
+            let message = format!("@{}:1:2@x", id);
+            let t = now + Duration::from_millis(id);
+            assert!(r.push(fragment(&message), t).is_none());
+        }
+
+        let lost = r.expire(now);
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].id, 0);
+        assert_eq!(lost[0].missing, vec![2]);
+        assert_eq!(r.flush().len(), MAX_PENDING);
+    }
+
+    #[test]
+    fn expire() {
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert!(r.push(fragment("@7:1:3@a"), now).is_none());
+        assert!(r.expire(now + Duration::from_secs(29)).is_empty());
+
+        let lost = r.expire(now + Duration::from_secs(30));
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].id, 7);
+        assert_eq!(lost[0].missing, vec![2, 3]);
+        assert_eq!(lost[0].notification.message, "a");
+    }
+
+    fn tmp(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("collectd-prv-reassemble-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn save_load() {
+        let path = tmp("save_load.state");
+        let now = Instant::now();
+
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        assert!(r.push(fragment("@1:1:3@a \"b\" "), now).is_none());
+        assert!(r.push(fragment("@1:3:3@c"), now).is_none());
+        assert!(r.push(fragment("@2:2:2@y"), now).is_none());
+        r.save(&path, now + Duration::from_secs(10)).unwrap();
+
+        // a missing file is empty
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        r.load(&tmp("missing.state"), Instant::now()).unwrap();
+        assert!(r.flush().is_empty());
+
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        r.load(&path, Instant::now()).unwrap();
+        let n = r.push(fragment("@1:2:3@b "), Instant::now()).unwrap();
+        assert_eq!(n.message, "a \"b\" b c");
+        assert_eq!(n.host, "h");
+
+        let lost = r.flush();
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].id, 2);
+        assert_eq!(lost[0].missing, vec![1]);
+    }
+
+    #[test]
+    fn load_age() {
+        let path = tmp("load_age.state");
+        let now = Instant::now();
+
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        assert!(r.push(fragment("@1:1:2@x"), now).is_none());
+        r.save(&path, now + Duration::from_secs(20)).unwrap();
+
+        // the set was saved 20 seconds after the first fragment
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        let now = Instant::now();
+        r.load(&path, now).unwrap();
+        assert!(r.expire(now + Duration::from_secs(5)).is_empty());
+        assert_eq!(r.expire(now + Duration::from_secs(11)).len(), 1);
+    }
+
+    #[test]
+    fn load_invalid() {
+        let path = tmp("invalid.state");
+        fs::write(
+            &path,
+            "x PUTNOTIF host=h plugin=p type=t message=\"@1:1:2@a\"\n\
+             1700000000 PUTVAL x\n\
+             1700000000\n\
+             1700000000 PUTNOTIF host=h plugin=p type=t message=\"@2:1:2@b\"\n",
+        )
+        .unwrap();
+
+        let mut r = Reassembler::new(Duration::from_secs(30));
+        r.load(&path, Instant::now()).unwrap();
+        let lost = r.flush();
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].id, 2);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// command restart policy
+///
+/// The restart delay doubles after each restart, from the min delay up to
+/// the max delay. The delay is reset if the command ran for longer than the
+/// max delay. Restarting stops when the max number of restarts is reached
+/// within the restart window.
+#[derive(Debug)]
+pub struct Restart {
+    min_delay: Duration,
+    max_delay: Duration,
+    max_restarts: usize,
+    window: Duration,
+    delay: Duration,
+    restarts: VecDeque<Instant>,
+}
+
+impl Restart {
+    /// create a restart policy: a max of 0 restarts is unlimited
+    pub fn new(
+        min_delay: Duration,
+        max_delay: Duration,
+        max_restarts: usize,
+        window: Duration,
+    ) -> Self {
+        Restart {
+            min_delay,
+            max_delay: max_delay.max(min_delay),
+            max_restarts,
+            window,
+            delay: min_delay,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// delay before restarting a command started at `started`
+    ///
+    /// Returns `None` if the command should not be restarted.
+    pub fn next(&mut self, started: Instant, now: Instant) -> Option<Duration> {
+        while let Some(&t) = self.restarts.front() {
+            if now.duration_since(t) < self.window {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+
+        if self.max_restarts > 0 && self.restarts.len() >= self.max_restarts {
+            return None;
+        }
+
+        if now.duration_since(started) > self.max_delay {
+            self.delay = self.min_delay;
+        }
+
+        let delay = self.delay;
+        self.delay = self.delay.saturating_mul(2).min(self.max_delay);
+
+        self.restarts.push_back(now + delay);
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn backoff() {
+        let mut r = Restart::new(secs(1), secs(8), 0, secs(60));
+        let t0 = Instant::now();
+
+        let delays: Vec<_> = (0..6).map(|_| r.next(t0, t0)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(secs(1)),
+                Some(secs(2)),
+                Some(secs(4)),
+                Some(secs(8)),
+                Some(secs(8)),
+                Some(secs(8))
+            ]
+        );
+    }
+
+    #[test]
+    fn max_delay_below_min_delay() {
+        let mut r = Restart::new(secs(4), secs(1), 0, secs(60));
+        let t0 = Instant::now();
+
+        assert_eq!(r.next(t0, t0), Some(secs(4)));
+        assert_eq!(r.next(t0, t0), Some(secs(4)));
+    }
+
+    #[test]
+    fn reset() {
+        let mut r = Restart::new(secs(1), secs(8), 0, secs(60));
+        let t0 = Instant::now();
+
+        for _ in 0..4 {
+            r.next(t0, t0);
+        }
+
+        // a command running for the max delay is not reset
+        assert_eq!(r.next(t0, t0 + secs(8)), Some(secs(8)));
+
+        // a command running for longer than the max delay is reset
+        let now = t0 + secs(100);
+        assert_eq!(r.next(now - secs(9), now), Some(secs(1)));
+        assert_eq!(r.next(now, now), Some(secs(2)));
+    }
+
+    #[test]
+    fn max_restarts() {
+        let mut r = Restart::new(secs(1), secs(1), 3, secs(60));
+        let t0 = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(r.next(t0, t0), Some(secs(1)));
+        }
+        assert_eq!(r.next(t0, t0), None);
+
+        // restarts are counted from the restart time over the window
+        assert_eq!(r.next(t0, t0 + secs(60)), None);
+        let now = t0 + secs(61);
+        for _ in 0..3 {
+            assert_eq!(r.next(now, now), Some(secs(1)));
+        }
+        assert_eq!(r.next(now, now), None);
+    }
+}
@@ -0,0 +1,181 @@
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// sed-style substitution: `s/<regex>/<replacement>/[gi]`
+///
+/// Any character may be used as the delimiter. The replacement may refer to
+/// the match as `&` or `\0` and to capture groups as `\1` to `\9`. Other
+/// escaped characters are literal: `\n` is `n`, since messages are single
+/// lines. Flags: `g` replaces all matches, `i` ignores case.
+#[derive(Clone, Debug)]
+pub struct Rewrite {
+    pub regex: Regex,
+    pub replacement: String,
+    pub global: bool,
+}
+
+impl Rewrite {
+    pub fn apply<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        if self.global {
+            self.regex.replace_all(message, self.replacement.as_str())
+        } else {
+            self.regex.replace(message, self.replacement.as_str())
+        }
+    }
+}
+
+impl FromStr for Rewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rewrite rule: {}", s);
+
+        let mut chars = s.strip_prefix('s').ok_or_else(invalid)?.chars();
+        let delimiter = chars.next().filter(|c| *c != '\\').ok_or_else(invalid)?;
+        let rest = chars.as_str();
+
+        let (pattern, rest) = split(rest, delimiter).ok_or_else(invalid)?;
+        let (replacement, flags) = split(rest, delimiter).ok_or_else(invalid)?;
+
+        let mut global = false;
+        let mut builder = RegexBuilder::new(&pattern);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => {
+                    builder.case_insensitive(true);
+                }
+                _ => return Err(format!("invalid rewrite flag: {}", flag)),
+            }
+        }
+
+        Ok(Rewrite {
+            regex: builder.build().map_err(|err| err.to_string())?,
+            replacement: replacement_syntax(&replacement),
+            global,
+        })
+    }
+}
+
+// split at the first unescaped delimiter
+//
+// An escaped delimiter is literal: punctuation stays escaped, as both the
+// regex and the replacement syntax treat escaped punctuation as literal,
+// except `\<` and `\>` which are word boundaries.
+fn split(s: &str, delimiter: char) -> Option<(String, &str)> {
+    let mut part = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if c == delimiter => return Some((part, &s[i + c.len_utf8()..])),
+            '\\' => match chars.next() {
+                Some((_, c)) if c == delimiter && !escapable(c) => part.push(c),
+                Some((_, c)) => {
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => part.push('\\'),
+            },
+            _ => part.push(c),
+        }
+    }
+    None
+}
+
+fn escapable(c: char) -> bool {
+    c.is_ascii_punctuation() && c != '<' && c != '>'
+}
+
+// convert sed replacement references to the regex crate syntax
+fn replacement_syntax(s: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => replacement.push_str("${0}"),
+            '$' => replacement.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(n @ '0'..='9') => {
+                    replacement.push_str("${");
+                    replacement.push(n);
+                    replacement.push('}');
+                }
+                Some('$') => replacement.push_str("$$"),
+                Some(c) => replacement.push(c),
+                None => replacement.push('\\'),
+            },
+            _ => replacement.push(c),
+        }
+    }
+    replacement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(rule: &str, message: &str) -> String {
+        rule.parse::<Rewrite>().unwrap().apply(message).into_owned()
+    }
+
+    #[test]
+    fn replace() {
+        assert_eq!(rewrite("s/b/x/", "abcb"), "axcb");
+        assert_eq!(rewrite("s/b/x/g", "abcb"), "axcx");
+        assert_eq!(rewrite("s/B/x/i", "abcb"), "axcb");
+        assert_eq!(rewrite("s/B/x/gi", "abcb"), "axcx");
+        assert_eq!(rewrite("s/^[0-9]+ //", "123 message"), "message");
+        assert_eq!(rewrite("s/z/x/", "abc"), "abc");
+    }
+
+    #[test]
+    fn delimiter() {
+        assert_eq!(rewrite("s|/usr/local/||g", "/usr/local/bin"), "bin");
+        assert_eq!(rewrite("s#a#b#", "aa"), "ba");
+        assert_eq!(rewrite("s/\\/usr\\//\\/opt\\//", "/usr/bin"), "/opt/bin");
+        assert_eq!(rewrite("s|a\\|b|x|", "a|b"), "x");
+        assert_eq!(rewrite("s.a\\..x.", "a.ab"), "xab");
+        assert_eq!(rewrite("s&a&\\&&", "ab"), "&b");
+        assert_eq!(rewrite("s$a$\\$$", "ab"), "$b");
+        assert_eq!(rewrite("s<a\\<b<x<", "a<b"), "x");
+        assert_eq!(rewrite("sxa\\xbxyx", "axb"), "y");
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(rewrite("s/b+/[&]/", "abbc"), "a[bb]c");
+        assert_eq!(rewrite("s/b+/[\\0]/", "abbc"), "a[bb]c");
+        assert_eq!(rewrite("s/(a)(b)/\\2\\1/", "abc"), "bac");
+        assert_eq!(rewrite("s/(a)/\\10/", "abc"), "a0bc");
+        assert_eq!(rewrite("s/(a)/\\2/", "abc"), "bc");
+    }
+
+    #[test]
+    fn literals() {
+        // not regex crate references
+        assert_eq!(rewrite("s/a/$1/", "abc"), "$1bc");
+        assert_eq!(rewrite("s/(a)/${1}$/", "abc"), "${1}$bc");
+        assert_eq!(rewrite("s/a/\\$/", "abc"), "$bc");
+        assert_eq!(rewrite("s/a/\\&/", "abc"), "&bc");
+        assert_eq!(rewrite("s/a/\\\\/", "abc"), "\\bc");
+        assert_eq!(rewrite("s/a/\\n/", "abc"), "nbc");
+    }
+
+    #[test]
+    fn invalid() {
+        for rule in [
+            "",
+            "s",
+            "s/a",
+            "s/a/b",
+            "x/a/b/",
+            "s\\a\\b\\",
+            "s/a/b/x",
+            "s/a/b\\/",
+            "s/(/b/",
+        ] {
+            assert!(rule.parse::<Rewrite>().is_err(), "{}", rule);
+        }
+    }
+}
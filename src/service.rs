@@ -0,0 +1,131 @@
+use crate::DATA_MAX_LEN;
+use std::fmt;
+use std::str::FromStr;
+
+/// collectd service: `<plugin>[-<plugin_instance>]/<type>[-<type_instance>]`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Service {
+    pub plugin: String,
+    pub plugin_instance: String,
+    pub ctype: String,
+    pub type_instance: String,
+}
+
+impl FromStr for Service {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (plugin, ctype) = s
+            .split_once('/')
+            .ok_or_else(|| format!("invalid plugin/type: no `/` found in `{}`", s))?;
+
+        let (plugin, plugin_instance) = plugin.split_once('-').unwrap_or((plugin, ""));
+        let (ctype, type_instance) = ctype.split_once('-').unwrap_or((ctype, ""));
+
+        // an instance follows the `-`
+        if plugin.is_empty() || ctype.is_empty() || s.split('/').any(|part| part.ends_with('-')) {
+            return Err(format!("invalid service: {}", s));
+        }
+
+        for part in [plugin, plugin_instance, ctype, type_instance] {
+            if part.len() >= DATA_MAX_LEN || part.contains('/') {
+                return Err(format!("invalid service: {}", s));
+            }
+        }
+
+        Ok(Service {
+            plugin: plugin.to_string(),
+            plugin_instance: plugin_instance.to_string(),
+            ctype: ctype.to_string(),
+            type_instance: type_instance.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.plugin)?;
+        if !self.plugin_instance.is_empty() {
+            write!(f, "-{}", self.plugin_instance)?;
+        }
+        write!(f, "/{}", self.ctype)?;
+        if !self.type_instance.is_empty() {
+            write!(f, "-{}", self.type_instance)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(plugin: &str, plugin_instance: &str, ctype: &str, type_instance: &str) -> Service {
+        Service {
+            plugin: plugin.to_string(),
+            plugin_instance: plugin_instance.to_string(),
+            ctype: ctype.to_string(),
+            type_instance: type_instance.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("stdout/prv".parse(), Ok(service("stdout", "", "prv", "")));
+        assert_eq!(
+            "tail-app/syslog-err".parse(),
+            Ok(service("tail", "app", "syslog", "err"))
+        );
+
+        // the instance begins at the first `-`
+        assert_eq!(
+            "tail-a-b-c/syslog-d-e".parse(),
+            Ok(service("tail", "a-b-c", "syslog", "d-e"))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "stdout",
+            "stdout-prv",
+            "/prv",
+            "stdout/",
+            "-a/prv",
+            "stdout/-a",
+            "tail-/syslog",
+            "tail/syslog-",
+            "tail-/syslog-",
+            "a/b/c",
+            "tail-a/b/syslog",
+        ] {
+            assert!(s.parse::<Service>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn max_len() {
+        let max = "x".repeat(DATA_MAX_LEN - 1);
+        let over = "x".repeat(DATA_MAX_LEN);
+
+        assert!(format!("{}-{}/{}-{}", max, max, max, max)
+            .parse::<Service>()
+            .is_ok());
+        assert!(format!("{}/prv", over).parse::<Service>().is_err());
+        assert!(format!("tail-{}/prv", over).parse::<Service>().is_err());
+        assert!(format!("tail/{}", over).parse::<Service>().is_err());
+        assert!(format!("tail/prv-{}", over).parse::<Service>().is_err());
+    }
+
+    #[test]
+    fn display() {
+        for s in [
+            "stdout/prv",
+            "tail-a-b/syslog",
+            "tail/syslog-err",
+            "a-b/c-d",
+        ] {
+            assert_eq!(s.parse::<Service>().unwrap().to_string(), s);
+        }
+    }
+}
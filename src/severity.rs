@@ -0,0 +1,35 @@
+use crate::notification::Severity;
+use regex::Regex;
+use std::str::FromStr;
+
+/// assigns a severity to messages matching a regular expression
+///
+/// Rules are written as `<severity>=<regex>`, e.g. `failure=(?i)panic`.
+#[derive(Clone, Debug)]
+pub struct SeverityRule {
+    pub severity: Severity,
+    pub regex: Regex,
+}
+
+impl FromStr for SeverityRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (severity, regex) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid severity rule: no `=` found in `{}`", s))?;
+
+        Ok(SeverityRule {
+            severity: severity.parse()?,
+            regex: Regex::new(regex).map_err(|err| err.to_string())?,
+        })
+    }
+}
+
+/// severity of the first rule matching the message
+pub fn classify(rules: &[SeverityRule], message: &str) -> Option<Severity> {
+    rules
+        .iter()
+        .find(|rule| rule.regex.is_match(message))
+        .map(|rule| rule.severity)
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// signals stopping this process when no command is run
+pub const EXIT_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+// last signal received or 0
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn record(sig: libc::c_int) {
+    RECEIVED.store(sig, Ordering::SeqCst);
+}
+
+/// catch the signals instead of terminating the process
+///
+/// The signals are checked with `received()`. The handler is reset when a
+/// signal is caught: a repeated signal terminates a process blocked in a
+/// write.
+pub fn catch(signals: &[libc::c_int]) {
+    for &sig in signals {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = record as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(sig, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// last caught signal
+pub fn received() -> Option<libc::c_int> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
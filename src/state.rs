@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// read positions of followed files
+///
+/// The state file contains one line per followed file:
+///
+/// ```text
+/// <inode> <offset> <path>
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    path: PathBuf,
+    positions: BTreeMap<PathBuf, (u64, u64)>,
+    dirty: bool,
+}
+
+impl State {
+    /// read the state file
+    ///
+    /// A missing state file is empty. Invalid lines are ignored.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let positions = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ' ');
+                let ino = fields.next()?.parse().ok()?;
+                let offset = fields.next()?.parse().ok()?;
+                let file = fields.next().filter(|f| !f.is_empty())?;
+                Some((PathBuf::from(file), (ino, offset)))
+            })
+            .collect();
+
+        Ok(State {
+            path: path.to_path_buf(),
+            positions,
+            dirty: false,
+        })
+    }
+
+    /// inode and offset of a followed file
+    pub fn get(&self, file: &Path) -> Option<(u64, u64)> {
+        self.positions.get(file).copied()
+    }
+
+    pub fn set(&mut self, file: &Path, position: (u64, u64)) {
+        if self.positions.get(file) != Some(&position) {
+            self.positions.insert(file.to_path_buf(), position);
+            self.dirty = true;
+        }
+    }
+
+    /// write the state file if a position has changed
+    ///
+    /// The state is written to a temporary file and renamed over the state
+    /// file.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let mut file = fs::File::create(&tmp)?;
+        for (path, (ino, offset)) in &self.positions {
+            writeln!(file, "{} {} {}", ino, offset, path.display())?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("collectd-prv-state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = tmp("round_trip.state");
+
+        let mut state = State::load(&path).unwrap();
+        assert_eq!(state.get(Path::new("/var/log/a.log")), None);
+
+        state.set(Path::new("/var/log/a.log"), (1, 100));
+        state.set(Path::new("/var/log/my app/b c.log"), (2, 200));
+        state.save().unwrap();
+
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.get(Path::new("/var/log/a.log")), Some((1, 100)));
+        assert_eq!(
+            state.get(Path::new("/var/log/my app/b c.log")),
+            Some((2, 200))
+        );
+    }
+
+    #[test]
+    fn save_changed() {
+        let path = tmp("save_changed.state");
+
+        // an unchanged state is not written
+        let mut state = State::load(&path).unwrap();
+        state.save().unwrap();
+        assert!(!path.exists());
+
+        state.set(Path::new("a.log"), (1, 1));
+        state.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 1 a.log\n");
+
+        state.set(Path::new("a.log"), (1, 1));
+        fs::remove_file(&path).unwrap();
+        state.save().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let path = tmp("invalid.state");
+        fs::write(
+            &path,
+            "1 10 valid.log\n\
+             x 10 bad-ino.log\n\
+             1 x bad-offset.log\n\
+             1 10\n\
+             1 10 \n\
+             \n\
+             2 20 also valid.log\n",
+        )
+        .unwrap();
+
+        let state = State::load(&path).unwrap();
+        assert_eq!(state.get(Path::new("valid.log")), Some((1, 10)));
+        assert_eq!(state.get(Path::new("also valid.log")), Some((2, 20)));
+        assert_eq!(state.get(Path::new("bad-ino.log")), None);
+        assert_eq!(state.get(Path::new("bad-offset.log")), None);
+        assert_eq!(state.positions.len(), 2);
+    }
+}
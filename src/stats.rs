@@ -0,0 +1,89 @@
+use crate::value::{Value, ValueList};
+
+/// collectd-prv counters
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub lines: u64,
+    pub bytes: u64,
+    pub filtered: u64,
+    pub fragments: u64,
+    pub discarded_messages: u64,
+    pub discarded_fragments: u64,
+    /// commands discarded by the output
+    pub dropped: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats::default()
+    }
+
+    /// counters as value lists
+    ///
+    /// The host, time, interval and plugin are copied from the template.
+    /// Counters are reported as `derive` values and the current rate limit
+    /// window count as a `gauge`.
+    pub fn values(&self, template: &ValueList, window: usize) -> Vec<ValueList> {
+        let value = |ctype: &str, type_instance: &str, v: Value| ValueList {
+            ctype: ctype.to_string(),
+            type_instance: type_instance.to_string(),
+            values: vec![v],
+            ..template.clone()
+        };
+
+        let derive =
+            |type_instance, v: u64| value("derive", type_instance, Value::Derive(v as i64));
+
+        vec![
+            derive("lines_read", self.lines),
+            derive("bytes_read", self.bytes),
+            derive("lines_filtered", self.filtered),
+            derive("fragments_emitted", self.fragments),
+            derive("messages_discarded", self.discarded_messages),
+            derive("fragments_discarded", self.discarded_fragments),
+            derive("commands_dropped", self.dropped),
+            value("gauge", "window_count", Value::Gauge(window as f64)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let template = ValueList {
+            host: "host".to_string(),
+            time: 1700000000,
+            interval: 10.0,
+            plugin: "collectd_prv".to_string(),
+            ..Default::default()
+        };
+        let stats = Stats {
+            lines: 5,
+            bytes: 100,
+            discarded_messages: 2,
+            ..Default::default()
+        };
+
+        let commands: Vec<String> = stats
+            .values(&template, 3)
+            .iter()
+            .map(|vl| vl.to_string())
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                "PUTVAL host/collectd_prv/derive-lines_read interval=10 1700000000:5",
+                "PUTVAL host/collectd_prv/derive-bytes_read interval=10 1700000000:100",
+                "PUTVAL host/collectd_prv/derive-lines_filtered interval=10 1700000000:0",
+                "PUTVAL host/collectd_prv/derive-fragments_emitted interval=10 1700000000:0",
+                "PUTVAL host/collectd_prv/derive-messages_discarded interval=10 1700000000:2",
+                "PUTVAL host/collectd_prv/derive-fragments_discarded interval=10 1700000000:0",
+                "PUTVAL host/collectd_prv/derive-commands_dropped interval=10 1700000000:0",
+                "PUTVAL host/collectd_prv/gauge-window_count interval=10 1700000000:3",
+            ]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::input::Record;
+use crate::notification::Severity;
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone};
+
+/// parse an RFC 5424 or RFC 3164 syslog line
+///
+/// The `<PRI>` header is optional. RFC 3164 timestamps are in local time
+/// and may be replaced by an RFC 3339 timestamp. A line with a `<PRI>`
+/// header followed by a message only, such as `<3>disk failure`, is the
+/// message. Returns `None` if the line is not in syslog format.
+pub fn parse(line: &str) -> Option<Record> {
+    let (pri, rest) = match parse_pri(line) {
+        Some((pri, rest)) => (Some(pri), rest),
+        None => (None, line),
+    };
+
+    let mut record = match rest.strip_prefix("1 ") {
+        Some(rest5424) if pri.is_some() => parse_rfc5424(rest5424),
+        _ => parse_rfc3164(rest),
+    }
+    .or_else(|| pri.map(|_| Record::new(rest)))?;
+
+    record.severity = pri.map(|pri| severity(pri & 7));
+    Some(record)
+}
+
+/// map a syslog severity to a notification severity
+pub fn severity(n: u8) -> Severity {
+    match n {
+        0..=3 => Severity::Failure,
+        4 => Severity::Warning,
+        _ => Severity::Okay,
+    }
+}
+
+fn parse_pri(s: &str) -> Option<(u8, &str)> {
+    let rest = s.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    if end == 0 || end > 3 || !rest[..end].bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let pri: u8 = rest[..end].parse().ok()?;
+    if pri > 191 {
+        return None;
+    }
+    Some((pri, &rest[end + 1..]))
+}
+
+// VERSION SP TIMESTAMP SP HOSTNAME SP APP-NAME SP PROCID SP MSGID SP SD [SP MSG]
+fn parse_rfc5424(s: &str) -> Option<Record> {
+    let mut fields = s.splitn(6, ' ');
+    let timestamp = fields.next()?;
+    let host = fields.next()?;
+    let _app = fields.next()?;
+    let _procid = fields.next()?;
+    let _msgid = fields.next()?;
+    let rest = fields.next()?;
+
+    let time = match timestamp {
+        "-" => None,
+        _ => Some(DateTime::parse_from_rfc3339(timestamp).ok()?.timestamp()),
+    };
+
+    let message = skip_structured_data(rest)?;
+    let message = message.strip_prefix(' ').unwrap_or(message);
+    let message = message.strip_prefix('\u{feff}').unwrap_or(message);
+
+    Some(Record {
+        message: message.to_string(),
+        time: time.map(|t| t.max(0) as u64),
+        host: nil(host),
+        ..Default::default()
+    })
+}
+
+fn skip_structured_data(s: &str) -> Option<&str> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return Some(rest);
+    }
+
+    let mut rest = s;
+    while rest.starts_with('[') {
+        let mut quoted = false;
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = !quoted,
+                ']' if !quoted => {
+                    end = Some(i);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        rest = &rest[end? + 1..];
+    }
+
+    if rest.len() == s.len() {
+        return None;
+    }
+    Some(rest)
+}
+
+// TIMESTAMP SP HOSTNAME SP MSG
+//
+// Local senders (syslog(3), logger) omit the hostname: the message begins
+// with the tag, `TAG:` or `TAG[PID]:`.
+fn parse_rfc3164(s: &str) -> Option<Record> {
+    let (time, rest) = parse_bsd_timestamp(s).or_else(|| {
+        let (timestamp, rest) = s.split_once(' ')?;
+        let t = DateTime::parse_from_rfc3339(timestamp).ok()?.timestamp();
+        Some((t, rest))
+    })?;
+
+    let (host, message) = match rest.split_once(' ') {
+        Some((tag, _)) if is_tag(tag) => (None, rest),
+        Some((host, message)) => (nil(host), message),
+        None if is_tag(rest) => (None, rest),
+        None => return None,
+    };
+
+    Some(Record {
+        message: message.to_string(),
+        time: Some(time.max(0) as u64),
+        host,
+        ..Default::default()
+    })
+}
+
+// TAG: or TAG[PID]:
+fn is_tag(s: &str) -> bool {
+    let tag = match s.strip_suffix(':') {
+        Some(tag) => tag,
+        None => return false,
+    };
+
+    let tag = match tag.strip_suffix(']').and_then(|t| t.rsplit_once('[')) {
+        Some((tag, pid)) if !pid.is_empty() && pid.bytes().all(|c| c.is_ascii_digit()) => tag,
+        _ => tag,
+    };
+
+    !tag.is_empty() && !tag.contains([':', '[', ']'])
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Mmm dd hh:mm:ss: the year is the current year unless the timestamp would
+// be in the future
+fn parse_bsd_timestamp(s: &str) -> Option<(i64, &str)> {
+    let timestamp = s.get(..15)?;
+    let rest = s.get(15..)?.strip_prefix(' ')?;
+
+    let month = MONTHS.iter().position(|&m| Some(m) == timestamp.get(..3))? as u32 + 1;
+    let day: u32 = timestamp.get(3..6)?.trim_start().parse().ok()?;
+    let mut hms = timestamp.get(7..)?.splitn(3, ':');
+    let hour: u32 = hms.next()?.parse().ok()?;
+    let min: u32 = hms.next()?.parse().ok()?;
+    let sec: u32 = hms.next()?.parse().ok()?;
+
+    let now = Local::now();
+    let local = |year| {
+        let t = NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, min, sec)?;
+        Local.from_local_datetime(&t).earliest()
+    };
+
+    let mut t = local(now.year())?;
+    if t > now + chrono::Duration::days(1) {
+        t = local(now.year() - 1)?;
+    }
+
+    Some((t.timestamp(), rest))
+}
+
+fn nil(s: &str) -> Option<String> {
+    match s {
+        "-" | "" => None,
+        _ => Some(s.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Timelike;
+
+    // local time of a timestamp without a year: timestamps more than a day
+    // in the future are in the previous year
+    fn local(month: u32, day: u32, h: u32, m: u32, s: u32) -> u64 {
+        let now = Local::now();
+        let at = |year| {
+            Local
+                .with_ymd_and_hms(year, month, day, h, m, s)
+                .earliest()
+                .unwrap()
+        };
+        let mut t = at(now.year());
+        if t > now + chrono::Duration::days(1) {
+            t = at(now.year() - 1);
+        }
+        t.timestamp() as u64
+    }
+
+    #[test]
+    fn pri() {
+        assert_eq!(parse_pri("<0>x"), Some((0, "x")));
+        assert_eq!(parse_pri("<191>x"), Some((191, "x")));
+        assert_eq!(parse_pri("<192>x"), None);
+        assert_eq!(parse_pri("<>x"), None);
+        assert_eq!(parse_pri("<1000>x"), None);
+        assert_eq!(parse_pri("<a>x"), None);
+        assert_eq!(parse_pri("x"), None);
+    }
+
+    #[test]
+    fn severities() {
+        for (n, severity) in [
+            (0, Severity::Failure),
+            (3, Severity::Failure),
+            (4, Severity::Warning),
+            (5, Severity::Okay),
+            (7, Severity::Okay),
+        ] {
+            assert_eq!(super::severity(n), severity);
+        }
+    }
+
+    #[test]
+    fn pri_only() {
+        let r = parse("<11>disk failure").unwrap();
+        assert_eq!(r.message, "disk failure");
+        assert_eq!(r.severity, Some(Severity::Failure));
+        assert_eq!(r.time, None);
+        assert_eq!(r.host, None);
+
+        let r = parse("<14>1 not rfc5424").unwrap();
+        assert_eq!(r.message, "1 not rfc5424");
+        assert_eq!(r.severity, Some(Severity::Okay));
+    }
+
+    #[test]
+    fn not_syslog() {
+        assert!(parse("disk failure").is_none());
+        assert!(parse("").is_none());
+        assert!(parse("<11").is_none());
+    }
+
+    #[test]
+    fn rfc5424() {
+        let r = parse(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"App]lication\"][x@1 a=\"\\\"\"] \
+             \u{feff}An application event",
+        )
+        .unwrap();
+        assert_eq!(r.message, "An application event");
+        assert_eq!(r.severity, Some(Severity::Okay));
+        assert_eq!(r.time, Some(1065910455));
+        assert_eq!(r.host.as_deref(), Some("mymachine.example.com"));
+    }
+
+    #[test]
+    fn rfc5424_nil() {
+        let r = parse("<11>1 - - app - - - disk failure").unwrap();
+        assert_eq!(r.message, "disk failure");
+        assert_eq!(r.severity, Some(Severity::Failure));
+        assert_eq!(r.time, None);
+        assert_eq!(r.host, None);
+
+        let r = parse("<11>1 - host app - - -").unwrap();
+        assert_eq!(r.message, "");
+        assert_eq!(r.host.as_deref(), Some("host"));
+    }
+
+    #[test]
+    fn structured_data() {
+        assert_eq!(skip_structured_data("- msg"), Some(" msg"));
+        assert_eq!(skip_structured_data("[a b=\"]\"] msg"), Some(" msg"));
+        assert_eq!(skip_structured_data("[a][b c=\"d\"]"), Some(""));
+        assert_eq!(skip_structured_data("[a b=\"\\\"]\"] msg"), Some(" msg"));
+        assert_eq!(skip_structured_data("[unterminated"), None);
+        assert_eq!(skip_structured_data("msg"), None);
+    }
+
+    #[test]
+    fn rfc3164() {
+        let r = parse("<13>Jan  1 00:00:00 host app: message").unwrap();
+        assert_eq!(r.time, Some(local(1, 1, 0, 0, 0)));
+        assert_eq!(r.host.as_deref(), Some("host"));
+        assert_eq!(r.message, "app: message");
+
+        // without PRI
+        let r = parse("Jan 10 12:34:56 host app: message").unwrap();
+        assert_eq!(r.severity, None);
+        assert_eq!(r.time, Some(local(1, 10, 12, 34, 56)));
+    }
+
+    #[test]
+    fn rfc3164_rfc3339_timestamp() {
+        let r = parse("<11>2003-10-11T22:14:15Z host app: message").unwrap();
+        assert_eq!(r.time, Some(1065910455));
+        assert_eq!(r.host.as_deref(), Some("host"));
+        assert_eq!(r.message, "app: message");
+    }
+
+    #[test]
+    fn bsd_timestamp() {
+        assert!(parse_bsd_timestamp("Jan  1 00:00:00").is_none());
+        assert!(parse_bsd_timestamp("Foo  1 00:00:00 x").is_none());
+        assert!(parse_bsd_timestamp("Jan 32 00:00:00 x").is_none());
+        assert!(parse_bsd_timestamp("Jan  1 24:00:00 x").is_none());
+        assert!(parse_bsd_timestamp("Jan  1 00:00 x").is_none());
+
+        let (_, rest) = parse_bsd_timestamp("Feb  3 04:05:06 rest").unwrap();
+        assert_eq!(rest, "rest");
+    }
+
+    #[test]
+    fn bsd_timestamp_year() {
+        // timestamps more than a day in the future are in the previous year
+        let now = Local::now();
+        let (t, _) = parse_bsd_timestamp("Dec 31 23:59:59 x").unwrap();
+        assert!(t <= (now + chrono::Duration::days(1)).timestamp());
+
+        let t = Local.timestamp_opt(t, 0).unwrap();
+        assert!(t.year() == now.year() || t.year() == now.year() - 1);
+        assert_eq!((t.month(), t.day(), t.hour()), (12, 31, 23));
+    }
+
+    #[test]
+    fn logger_socket() {
+        let r = parse("<13>Oct 15 21:24:31 myapp: test message").unwrap();
+        assert_eq!(r.host, None);
+        assert_eq!(r.message, "myapp: test message");
+        assert_eq!(r.severity, Some(Severity::Okay));
+
+        let r = parse("<11>Oct 15 21:24:31 myapp[23835]: disk failure").unwrap();
+        assert_eq!(r.host, None);
+        assert_eq!(r.message, "myapp[23835]: disk failure");
+        assert_eq!(r.severity, Some(Severity::Failure));
+    }
+
+    #[test]
+    fn rfc3164_hostname() {
+        let r = parse("<12>Oct 15 21:24:31 web1 myapp[1]: disk full").unwrap();
+        assert_eq!(r.host.as_deref(), Some("web1"));
+        assert_eq!(r.message, "myapp[1]: disk full");
+        assert_eq!(r.severity, Some(Severity::Warning));
+
+        let r = parse("<12>Oct 15 21:24:31 2001:db8:: myapp: disk full").unwrap();
+        assert_eq!(r.host.as_deref(), Some("2001:db8::"));
+        assert_eq!(r.message, "myapp: disk full");
+    }
+}
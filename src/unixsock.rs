@@ -0,0 +1,217 @@
+use crate::notification::Notification;
+use crate::output::Output;
+use crate::value::ValueList;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const BACKOFF_MIN: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// time to wait for the response to a command
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// collectd unixsock plugin output
+///
+/// Each command is acknowledged by collectd with a status line: a negative
+/// status means the command was rejected. If the connection fails, the
+/// socket is reconnected with exponential backoff and the command is
+/// resent. A command written before the response is lost or times out is
+/// not resent.
+///
+/// Reconnecting stops with an `Interrupted` error when `cancelled` returns
+/// true.
+pub struct Unixsock {
+    path: PathBuf,
+    conn: Option<(UnixStream, BufReader<UnixStream>)>,
+    backoff: Duration,
+    cancelled: Box<dyn Fn() -> bool + Send>,
+}
+
+impl fmt::Debug for Unixsock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Unixsock")
+            .field("path", &self.path)
+            .field("conn", &self.conn)
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Unixsock {
+    pub fn new(path: &Path, cancelled: impl Fn() -> bool + Send + 'static) -> Self {
+        Unixsock {
+            path: path.to_path_buf(),
+            conn: None,
+            backoff: BACKOFF_MIN,
+            cancelled: Box::new(cancelled),
+        }
+    }
+
+    fn retry(&mut self) -> io::Result<()> {
+        self.conn = None;
+        thread::sleep(self.backoff);
+        self.backoff = (self.backoff * 2).min(BACKOFF_MAX);
+
+        if (self.cancelled)() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!("{}: interrupted", self.path.display()),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn connect(&mut self) -> io::Result<&mut (UnixStream, BufReader<UnixStream>)> {
+        while self.conn.is_none() {
+            let stream = UnixStream::connect(&self.path).and_then(|s| {
+                s.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+                Ok((s.try_clone()?, s))
+            });
+            match stream {
+                Ok((w, r)) => self.conn = Some((w, BufReader::new(r))),
+                Err(err) => {
+                    eprintln!("{}: {}", self.path.display(), err);
+                    self.retry()?;
+                }
+            }
+        }
+
+        Ok(self.conn.as_mut().unwrap())
+    }
+
+    fn command(&mut self, command: &str) -> io::Result<()> {
+        loop {
+            let (w, r) = self.connect()?;
+
+            if w.write_all(format!("{}\n", command).as_bytes()).is_err() {
+                self.retry()?;
+                continue;
+            }
+
+            // the command may have been accepted: the connection is
+            // reopened for the next command
+            let mut response = String::new();
+            match r.read_line(&mut response) {
+                Ok(0) | Err(_) => {
+                    eprintln!("{}: no response", self.path.display());
+                    self.conn = None;
+                    return Ok(());
+                }
+                Ok(_) => self.backoff = BACKOFF_MIN,
+            }
+
+            let response = response.trim_end();
+            let status: i64 = response
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(-1);
+
+            if status < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    response.to_string(),
+                ));
+            }
+
+            return Ok(());
+        }
+    }
+}
+
+impl Output for Unixsock {
+    fn notify(&mut self, n: &Notification) -> io::Result<()> {
+        self.command(&n.to_string())
+    }
+
+    fn putval(&mut self, vl: &ValueList) -> io::Result<()> {
+        self.command(&vl.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+
+    fn tmp(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("collectd-prv-unixsock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // reply to each command, returning the received commands
+    fn serve(listener: UnixListener, responses: &[&str]) -> thread::JoinHandle<Vec<String>> {
+        let responses: Vec<String> = responses.iter().map(|r| r.to_string()).collect();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut w = stream.try_clone().unwrap();
+            let mut r = BufReader::new(stream);
+            let mut commands = Vec::new();
+            for response in responses {
+                let mut line = String::new();
+                if r.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                commands.push(line.trim_end().to_string());
+                if response.is_empty() {
+                    // close the connection without a response
+                    break;
+                }
+                w.write_all(response.as_bytes()).unwrap();
+            }
+            commands
+        })
+    }
+
+    #[test]
+    fn response() {
+        let path = tmp("response.sock");
+        let server = serve(
+            UnixListener::bind(&path).unwrap(),
+            &["0 Success\n", "-1 Parse error\n"],
+        );
+
+        let mut u = Unixsock::new(&path, || false);
+        assert!(u.command("PUTNOTIF message=\"a\"").is_ok());
+
+        let err = u.command("PUTNOTIF message=\"b\"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "-1 Parse error");
+
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["PUTNOTIF message=\"a\"", "PUTNOTIF message=\"b\""]
+        );
+    }
+
+    #[test]
+    fn no_response() {
+        let path = tmp("no_response.sock");
+        let server = serve(UnixListener::bind(&path).unwrap(), &[""]);
+
+        // the written command is not resent
+        let mut u = Unixsock::new(&path, || false);
+        assert!(u.command("PUTNOTIF message=\"a\"").is_ok());
+        assert_eq!(server.join().unwrap(), vec!["PUTNOTIF message=\"a\""]);
+    }
+
+    #[test]
+    fn cancelled() {
+        let path = tmp("cancelled.sock");
+
+        let mut u = Unixsock::new(&path, || true);
+        let err = u.command("PUTNOTIF message=\"a\"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
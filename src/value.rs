@@ -0,0 +1,140 @@
+use crate::notification::write_value;
+use std::fmt;
+
+/// data source value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Gauge(f64),
+    Derive(i64),
+    Counter(u64),
+    Absolute(u64),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Gauge(v) if v.is_nan() => f.write_str("U"),
+            Value::Gauge(v) => write!(f, "{}", v),
+            Value::Derive(v) => write!(f, "{}", v),
+            Value::Counter(v) | Value::Absolute(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// collectd value list
+///
+/// Formatting a value list produces an exec plugin PUTVAL command without
+/// the trailing newline.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueList {
+    pub host: String,
+    pub time: u64,
+    pub interval: f64,
+    pub plugin: String,
+    pub plugin_instance: String,
+    pub ctype: String,
+    pub type_instance: String,
+    pub values: Vec<Value>,
+}
+
+impl ValueList {
+    /// identifier: `<host>/<plugin>[-<plugin_instance>]/<type>[-<type_instance>]`
+    pub fn identifier(&self) -> String {
+        let mut id = format!("{}/{}", self.host, self.plugin);
+        if !self.plugin_instance.is_empty() {
+            id.push('-');
+            id.push_str(&self.plugin_instance);
+        }
+        id.push('/');
+        id.push_str(&self.ctype);
+        if !self.type_instance.is_empty() {
+            id.push('-');
+            id.push_str(&self.type_instance);
+        }
+        id
+    }
+}
+
+impl fmt::Display for ValueList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PUTVAL ")?;
+        write_value(f, &self.identifier())?;
+        write!(f, " interval={} {}", self.interval, self.time)?;
+        for v in &self.values {
+            write!(f, ":{}", v)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_list(values: Vec<Value>) -> ValueList {
+        ValueList {
+            host: "host".to_string(),
+            time: 1700000000,
+            interval: 10.0,
+            plugin: "stdout".to_string(),
+            ctype: "derive".to_string(),
+            type_instance: "lines_read".to_string(),
+            values,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn identifier() {
+        let mut vl = value_list(vec![]);
+        assert_eq!(vl.identifier(), "host/stdout/derive-lines_read");
+
+        vl.plugin_instance = "app".to_string();
+        assert_eq!(vl.identifier(), "host/stdout-app/derive-lines_read");
+
+        vl.type_instance.clear();
+        assert_eq!(vl.identifier(), "host/stdout-app/derive");
+    }
+
+    #[test]
+    fn putval() {
+        assert_eq!(
+            value_list(vec![Value::Derive(-3)]).to_string(),
+            "PUTVAL host/stdout/derive-lines_read interval=10 1700000000:-3"
+        );
+
+        let vl = ValueList {
+            interval: 0.5,
+            ..value_list(vec![
+                Value::Gauge(1.5),
+                Value::Counter(7),
+                Value::Absolute(8),
+            ])
+        };
+        assert_eq!(
+            vl.to_string(),
+            "PUTVAL host/stdout/derive-lines_read interval=0.5 1700000000:1.5:7:8"
+        );
+    }
+
+    #[test]
+    fn putval_quoted() {
+        let vl = ValueList {
+            host: "my host".to_string(),
+            ..value_list(vec![Value::Gauge(1.0)])
+        };
+        assert_eq!(
+            vl.to_string(),
+            "PUTVAL \"my host/stdout/derive-lines_read\" interval=10 1700000000:1"
+        );
+    }
+
+    #[test]
+    fn undefined() {
+        assert_eq!(Value::Gauge(f64::NAN).to_string(), "U");
+        assert_eq!(
+            value_list(vec![Value::Gauge(f64::NAN)]).to_string(),
+            "PUTVAL host/stdout/derive-lines_read interval=10 1700000000:U"
+        );
+    }
+}